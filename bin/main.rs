@@ -1,11 +1,25 @@
-use std::path::PathBuf;
+use std::{
+    fmt::Display,
+    path::{Path, PathBuf},
+    time::Duration,
+};
 
 use clap::{CommandFactory, Parser};
-use clap_info_rs::{ClapInfoHost, ClapScanner, InfoBundle};
+use clap_info_rs::{
+    ActivationSettings, ClapInfoHost, ClapInfoHostError, ClapScanner, DEFAULT_MAX_DEPTH,
+    InfoBundle, InfoPlugin, InputSignal, LogLevel, PluginMatcher, QueryOptions, RenderInput,
+    RenderSettings, ScanCache, ScanEntry, ScanFailure, TimedOut, run_with_timeout,
+};
 
 #[derive(Parser)]
-#[command(about = "A tool to display information about CLAP plugins")]
+#[command(
+    about = "A tool to display information about CLAP plugins",
+    args_conflicts_with_subcommands = true
+)]
 struct ClapInfoArgs {
+    #[command(subcommand)]
+    command: Option<ClapInfoCommand>,
+
     /// The path to the CLAP plugin to display information about
     path: Option<String>,
 
@@ -21,10 +35,178 @@ struct ClapInfoArgs {
     #[arg(long)]
     search_path: bool,
 
-    // TODO: If you set to -1 we will traverse all plugins.
+    /// Scan each CLAP file in a separate process, so a crashing plugin can't abort the scan
+    #[arg(long)]
+    out_of_process: bool,
+
+    /// Only display the plugin descriptors of the given CLAP, without instantiating plugins
+    /// (used by out-of-process scanning)
+    #[arg(long, hide = true)]
+    descriptors_only: bool,
+
+    /// Give up on a plugin when loading, instantiating, activating or probing an
+    /// extension takes longer than this many milliseconds
+    #[arg(long, value_name = "MS", value_parser = clap::value_parser!(u64).range(1..))]
+    timeout: Option<u64>,
+
+    /// Don't read nor write the scan cache
+    #[arg(long)]
+    no_cache: bool,
+
+    /// Discard the scan cache and scan every CLAP file again
+    #[arg(long, conflicts_with = "no_cache")]
+    rebuild_cache: bool,
+
+    /// How many directory levels below each search path to look for CLAP files
+    #[arg(long, default_value_t = DEFAULT_MAX_DEPTH)]
+    max_depth: usize,
+
     /// Choose which plugin to create (if the CLAP has more than one).
-    #[arg(short, long, default_value = "0")]
+    /// Use `all` (or -1) to query every plugin in turn.
+    #[arg(
+        short,
+        long,
+        default_value = "0",
+        allow_hyphen_values = true,
+        conflicts_with = "plugin_selection"
+    )]
+    which: Which,
+
+    /// Check that every parameter's value -> text -> value conversion round-trips
+    #[arg(long)]
+    params_roundtrip: bool,
+
+    /// Output parameters as a tree grouped by module path instead of a flat list
+    #[arg(long)]
+    params_tree: bool,
+
+    /// Run the bundle's preset-discovery providers and list the presets they find
+    #[arg(long)]
+    presets: bool,
+
+    /// The sample rate to activate plugins with
+    #[arg(long, value_name = "HZ", default_value_t = 48_000.0)]
+    sample_rate: f64,
+
+    /// The minimum number of frames per process call to activate plugins with
+    #[arg(long, value_name = "FRAMES", default_value_t = 32)]
+    min_frames: u32,
+
+    /// The maximum number of frames per process call to activate plugins with
+    #[arg(long, value_name = "FRAMES", default_value_t = 4096)]
+    max_frames: u32,
+
+    /// Also activate plugins at 44.1, 48, 88.2, 96 and 192 kHz and report the
+    /// extension values that change between them
+    #[arg(long)]
+    activation_matrix: bool,
+
+    /// Also repeat activate/deactivate and instantiate/destroy cycles this many
+    /// times on each queried plugin, to catch leaks and crashes
+    #[arg(long, value_name = "N", default_value_t = 0)]
+    lifecycle_stress: usize,
+
+    /// Only report the plugin log messages of at least this severity
+    /// (debug, info, warning, error or fatal)
+    #[arg(long, value_name = "LEVEL", default_value = "debug")]
+    log_level: LogLevel,
+
+    /// Also print the plugin log messages to stderr as they arrive
+    #[arg(long)]
+    log_to_stderr: bool,
+
+    /// Choose the plugin to create by its id (e.g. com.vendor.reverb), instead of --which
+    #[arg(long, value_name = "ID", group = "plugin_selection")]
+    plugin_id: Option<String>,
+
+    /// Choose the plugins to create by matching their id or name against a glob
+    /// pattern (`*` and `?` wildcards), instead of --which
+    #[arg(long, value_name = "GLOB", group = "plugin_selection")]
+    plugin_match: Option<String>,
+
+    /// Choose the plugins to create by matching their id or name against a
+    /// regular expression, instead of --which
+    #[arg(long, value_name = "REGEX", group = "plugin_selection")]
+    plugin_regex: Option<String>,
+}
+
+#[derive(clap::Subcommand)]
+enum ClapInfoCommand {
+    /// Render a test signal through a plugin and write each output port to a WAV file
+    Render(RenderArgs),
+}
+
+#[derive(clap::Args)]
+struct RenderArgs {
+    /// The path to the CLAP plugin to render through
+    path: String,
+
+    /// Choose which plugin to render through (if the CLAP has more than one)
+    #[arg(short, long, default_value_t = 0)]
     which: usize,
+
+    /// Choose the plugin to render through by its id, instead of --which
+    #[arg(long, value_name = "ID", conflicts_with = "which")]
+    plugin_id: Option<String>,
+
+    /// The signal fed to the main input port: silence, impulse, sine, noise,
+    /// or the path of a WAV file
+    #[arg(short, long, default_value = "impulse")]
+    input: RenderInput,
+
+    /// The frequency of the sine input
+    #[arg(long, value_name = "HZ", default_value_t = 440.0)]
+    frequency: f32,
+
+    /// How many seconds to render [default: the length of the WAV input, or 1]
+    #[arg(long)]
+    seconds: Option<f64>,
+
+    /// The sample rate to render at, in whole Hz [default: the sample rate of the WAV input, or 48000]
+    #[arg(long, value_name = "HZ")]
+    sample_rate: Option<f64>,
+
+    /// The number of frames per process call
+    #[arg(long, value_name = "FRAMES", default_value_t = 512)]
+    block_size: u32,
+
+    /// The directory to write the WAV files to
+    #[arg(short, long, value_name = "DIR", default_value = ".")]
+    out_dir: PathBuf,
+
+    /// Give up when instantiating, activating or processing a block takes longer
+    /// than this many milliseconds
+    #[arg(long, value_name = "MS", value_parser = clap::value_parser!(u64).range(1..))]
+    timeout: Option<u64>,
+
+    /// Only report the plugin log messages of at least this severity
+    /// (debug, info, warning, error or fatal)
+    #[arg(long, value_name = "LEVEL", default_value = "debug")]
+    log_level: LogLevel,
+
+    /// Also print the plugin log messages to stderr as they arrive
+    #[arg(long)]
+    log_to_stderr: bool,
+}
+
+#[derive(Clone, Copy)]
+enum Which {
+    Index(usize),
+    All,
+}
+
+impl std::str::FromStr for Which {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "all" | "-1" => Ok(Which::All),
+            _ => value
+                .parse()
+                .map(Which::Index)
+                .map_err(|_| format!("expected a plugin index or `all`, got `{}`", value)),
+        }
+    }
 }
 
 #[derive(serde::Serialize)]
@@ -33,63 +215,371 @@ struct ClapInfoResult<T: ?Sized + serde::Serialize> {
     result: T,
 }
 
+#[derive(serde::Serialize)]
+struct TimedOutBundle {
+    path: String,
+    #[serde(flatten)]
+    timed_out: TimedOut,
+}
+
+#[derive(serde::Serialize)]
+struct ClapInfoFailure<'a> {
+    action: &'static str,
+    error: &'a ClapInfoHostError,
+}
+
+// Exit codes
+const EXIT_BUNDLE_ERROR: i32 = 1;
+// Same as clap for the command lines it rejects itself
+const EXIT_USAGE: i32 = 2;
+const EXIT_PLUGIN_ERROR: i32 = 3;
+// The hung plugin call is still running on its worker thread, so the process exits
+// right away instead of waiting for it
+const EXIT_TIMED_OUT: i32 = 4;
+const EXIT_OUTPUT_ERROR: i32 = 5;
+
 fn main() {
-    let args = ClapInfoArgs::parse();
-
-    if let Some(ref path) = args.path {
-        match ClapScanner::get_bundle(PathBuf::from(path)) {
-            Some((bundle, file)) => {
-                let mut info = InfoBundle::new(path.to_owned(), &bundle, Some(file));
-                let mut host = ClapInfoHost::new(bundle);
-                let mut plugin_info = info.get_plugin_mut(args.which);
-                host.query_extensions(args.which, &mut plugin_info).unwrap();
-
-                let result = ClapInfoResult {
-                    action: "display info for a CLAP plugin",
-                    result: info,
-                };
-                println!("{}", serde_json::to_string_pretty(&result).unwrap());
+    let mut args = ClapInfoArgs::parse();
+
+    if let Some(ClapInfoCommand::Render(render_args)) = args.command.take() {
+        render(render_args);
+        return;
+    }
+
+    let timeout = args.timeout.map(Duration::from_millis);
+
+    // CLAP requires the frame counts to be in [1, INT32_MAX]
+    if args.min_frames == 0
+        || args.min_frames > args.max_frames
+        || args.max_frames > i32::MAX as u32
+        || !args.sample_rate.is_finite()
+        || args.sample_rate <= 0.0
+    {
+        exit_usage(format!(
+            "Invalid activation settings: --sample-rate must be positive, --min-frames and \
+             --max-frames must be between 1 and {}, and --min-frames can't exceed --max-frames",
+            i32::MAX
+        ));
+    }
+
+    if let Some(path) = args.path.clone() {
+        let which = args.which;
+        let matcher = plugin_matcher(&args);
+        let options = QueryOptions {
+            params_roundtrip: args.params_roundtrip,
+            params_tree: args.params_tree,
+            log_level: args.log_level,
+            log_to_stderr: args.log_to_stderr,
+            activation: ActivationSettings {
+                sample_rate: args.sample_rate,
+                min_frames: args.min_frames,
+                max_frames: args.max_frames,
+            },
+            activation_matrix: args.activation_matrix,
+            lifecycle_stress: args.lifecycle_stress,
+        };
+        let descriptors_only = args.descriptors_only;
+        let presets = args.presets;
+        let action = if descriptors_only {
+            "display descriptions for a CLAP"
+        } else {
+            "display info for a CLAP plugin"
+        };
+
+        let bundle_path = path.clone();
+        let worker_options = options.clone();
+        // Load the bundle, and query the plugin selected by index right away. Plugins
+        // selected with --which all or a matcher are returned to be queried one by one.
+        let loaded = run_with_timeout(timeout, move |watchdog| {
+            watchdog.enter("load");
+            let (bundle, file) = ClapScanner::get_bundle(PathBuf::from(&path))?;
+            let mut info = InfoBundle::new(path, &bundle, Some(file))?;
+            if descriptors_only {
+                return Ok((info, Vec::new()));
+            }
+            let mut host = ClapInfoHost::new(bundle)
+                .with_watchdog(watchdog.clone())
+                .with_options(worker_options);
+            if presets {
+                host.query_preset_discovery(&mut info);
+            }
+            let indices = match (matcher, which) {
+                (Some(matcher), _) => host.find_plugins(&matcher)?,
+                (None, Which::Index(index)) => {
+                    let plugin_info = info.get_plugin_mut(index)?;
+                    host.query_extensions(index, plugin_info)?;
+                    Vec::new()
+                }
+                (None, Which::All) => (0..info.plugin_count()).collect(),
+            };
+            Ok::<_, ClapInfoHostError>((info, indices))
+        });
+
+        let (mut info, indices) = match loaded {
+            Ok(Ok(loaded)) => loaded,
+            Ok(Err(err)) => exit_with_error(action, &err),
+            Err(timed_out) => {
+                print_result(
+                    action,
+                    TimedOutBundle {
+                        path: bundle_path,
+                        timed_out,
+                    },
+                );
+                std::process::exit(EXIT_TIMED_OUT);
             }
-            None => {
-                eprintln!("Failed to get bundle info for {}", args.path.unwrap());
+        };
+
+        let mut first_error = None;
+        for index in indices {
+            let plugin_info = info
+                .get_plugin_mut(index)
+                .unwrap_or_else(|err| exit_with_error(action, &err));
+            if let Err(err) = query_plugin(&bundle_path, index, plugin_info, &options, timeout) {
+                first_error.get_or_insert(err);
             }
         }
+        print_result(action, info);
+        if let Some(err) = first_error {
+            std::process::exit(exit_code(&err));
+        }
     } else if args.search_path {
         let search_path = ClapScanner::get_search_paths();
-        let result = ClapInfoResult {
-            action: "display the CLAP plugin search path",
-            result: search_path,
-        };
-        println!("{}", serde_json::to_string_pretty(&result).unwrap());
+        print_result("display the CLAP plugin search path", search_path);
     } else if args.list_clap_files {
-        let clap_files = ClapScanner::installed_claps()
+        let clap_files = ClapScanner::installed_claps_with_max_depth(args.max_depth)
             .into_iter()
             .map(|p| p.display().to_string())
             .collect::<Vec<_>>();
 
-        let result = ClapInfoResult {
-            action: "display paths for installed claps",
-            result: clap_files,
-        };
-
-        println!("{}", serde_json::to_string_pretty(&result).unwrap());
+        print_result("display paths for installed claps", clap_files);
     } else if args.scan_clap_files {
-        let clap_bundles = ClapScanner::installed_claps()
-            .into_iter()
-            .filter_map(|clap_path| {
-                let path = clap_path.display().to_string();
-                ClapScanner::get_bundle(clap_path)
-                    .map(|(bundle, bundle_path)| InfoBundle::new(path, &bundle, Some(bundle_path)))
+        let clap_paths = ClapScanner::installed_claps_with_max_depth(args.max_depth);
+
+        let clap_info_exe = args.out_of_process.then(|| {
+            std::env::current_exe().unwrap_or_else(|err| {
+                eprintln!("Failed to get the clap-info executable path: {}", err);
+                std::process::exit(EXIT_BUNDLE_ERROR);
             })
-            .collect::<Vec<_>>();
+        });
 
-        let result = ClapInfoResult {
-            action: "display descriptions for installed claps",
-            result: clap_bundles,
-        };
+        let mut cache = (!args.no_cache).then(ScanCache::open_default);
+        if let Some(cache) = cache.as_mut().filter(|_| args.rebuild_cache) {
+            cache.clear();
+        }
+
+        let mut clap_bundles = Vec::new();
+        for clap_path in clap_paths {
+            if let Some(bundle) = cache.as_mut().and_then(|cache| cache.get(&clap_path)) {
+                clap_bundles.push(ScanEntry::Bundle(bundle.clone()));
+                continue;
+            }
+
+            let entry = scan_bundle(&clap_path, clap_info_exe.as_deref(), timeout);
+            // Failures aren't cached: they may be transient (e.g. a hang)
+            if let (Some(cache), ScanEntry::Bundle(bundle)) = (cache.as_mut(), &entry) {
+                cache.insert(&clap_path, bundle.clone());
+            }
+            clap_bundles.push(entry);
+        }
 
-        println!("{}", serde_json::to_string_pretty(&result).unwrap());
+        if let Some(Err(err)) = cache.map(|cache| cache.save()) {
+            eprintln!("Failed to save the scan cache: {}", err);
+        }
+
+        print_result("display descriptions for installed claps", clap_bundles);
     } else {
         println!("{}", ClapInfoArgs::command().render_help());
     }
 }
+
+fn print_result<T: serde::Serialize>(action: &'static str, result: T) {
+    match serde_json::to_string_pretty(&ClapInfoResult { action, result }) {
+        Ok(json) => println!("{}", json),
+        Err(err) => exit_with_error(action, &ClapInfoHostError::from(err)),
+    }
+}
+
+// Print the error as json and exit with its exit code
+fn exit_with_error(action: &'static str, error: &ClapInfoHostError) -> ! {
+    let failure = ClapInfoFailure { action, error };
+    match serde_json::to_string_pretty(&failure) {
+        Ok(json) => println!("{}", json),
+        Err(_) => eprintln!("{}", error),
+    }
+    std::process::exit(exit_code(error));
+}
+
+// The exit code for a failed query, depending on what failed
+fn exit_code(error: &ClapInfoHostError) -> i32 {
+    match error {
+        ClapInfoHostError::BundleNotFound(_)
+        | ClapInfoHostError::BundleLoadError { .. }
+        | ClapInfoHostError::MissingPluginFactory => EXIT_BUNDLE_ERROR,
+        ClapInfoHostError::TimedOut(_) => EXIT_TIMED_OUT,
+        ClapInfoHostError::SerializationError(_) | ClapInfoHostError::WavWriteError { .. } => {
+            EXIT_OUTPUT_ERROR
+        }
+        _ => EXIT_PLUGIN_ERROR,
+    }
+}
+
+// Print why the command line is invalid and exit
+fn exit_usage(message: impl Display) -> ! {
+    eprintln!("{}", message);
+    std::process::exit(EXIT_USAGE);
+}
+
+// Render the input signal through the plugin and print where the output ports were written
+fn render(args: RenderArgs) {
+    let action = "render a signal through a CLAP plugin";
+
+    let input = InputSignal::load(&args.input, args.frequency)
+        .unwrap_or_else(|err| exit_usage(format!("Invalid --input {}: {}", args.input, err)));
+    let wav = match &input {
+        InputSignal::Wav(wav) => Some(wav),
+        _ => None,
+    };
+    let input_rate = wav.map(|wav| wav.sample_rate as f64);
+    let sample_rate = args.sample_rate.or(input_rate).unwrap_or(48_000.0);
+    if let Some(input_rate) = input_rate.filter(|input_rate| *input_rate != sample_rate) {
+        // The input isn't resampled
+        exit_usage(format!(
+            "The sample rate of --input ({} Hz) doesn't match --sample-rate ({} Hz)",
+            input_rate, sample_rate
+        ));
+    }
+    let seconds = args
+        .seconds
+        .unwrap_or_else(|| wav.map_or(1.0, |wav| wav.duration_secs()));
+    let settings = RenderSettings {
+        input,
+        seconds,
+        sample_rate,
+        block_size: args.block_size,
+    };
+    if !settings.has_whole_sample_rate()
+        || settings.block_size == 0
+        || !seconds.is_finite()
+        || seconds < 0.0
+    {
+        exit_usage(
+            "Invalid render settings: --sample-rate must be a positive whole number of Hz, \
+             --block-size must be positive, and --seconds can't be negative",
+        );
+    }
+    let options = QueryOptions {
+        log_level: args.log_level,
+        log_to_stderr: args.log_to_stderr,
+        ..QueryOptions::default()
+    };
+    let path = args.path.clone();
+    let (which, plugin_id, out_dir) = (args.which, args.plugin_id, args.out_dir);
+    let rendered = run_with_timeout(args.timeout.map(Duration::from_millis), move |watchdog| {
+        watchdog.enter("load");
+        let (bundle, _) = ClapScanner::get_bundle(PathBuf::from(&path))?;
+        let mut host = ClapInfoHost::new(bundle)
+            .with_watchdog(watchdog.clone())
+            .with_options(options);
+        let index = match plugin_id {
+            Some(id) => host.find_plugins(&PluginMatcher::Id(id))?[0],
+            None => which,
+        };
+        host.render(index, &settings, &out_dir)
+    });
+
+    match rendered {
+        Ok(Ok(render)) => print_result(action, render),
+        Ok(Err(err)) => exit_with_error(action, &err),
+        Err(timed_out) => {
+            print_result(
+                action,
+                TimedOutBundle {
+                    path: args.path,
+                    timed_out,
+                },
+            );
+            std::process::exit(EXIT_TIMED_OUT);
+        }
+    }
+}
+
+// The plugin selection given by --plugin-id, --plugin-match or --plugin-regex, if any
+fn plugin_matcher(args: &ClapInfoArgs) -> Option<PluginMatcher> {
+    if let Some(id) = &args.plugin_id {
+        Some(PluginMatcher::Id(id.clone()))
+    } else if let Some(pattern) = &args.plugin_match {
+        Some(PluginMatcher::Glob(pattern.clone()))
+    } else if let Some(pattern) = &args.plugin_regex {
+        match PluginMatcher::regex(pattern) {
+            Ok(matcher) => Some(matcher),
+            Err(err) => exit_usage(format!("Invalid --plugin-regex: {}", err)),
+        }
+    } else {
+        None
+    }
+}
+
+// Query a plugin on its own worker, so a hanging plugin only loses its own entry:
+// the timeout is recorded as its error and the other plugins are still queried.
+// The error (or timeout) is also returned, to pick the exit code.
+fn query_plugin(
+    bundle_path: &str,
+    index: usize,
+    plugin_info: &mut InfoPlugin,
+    options: &QueryOptions,
+    timeout: Option<Duration>,
+) -> Result<(), ClapInfoHostError> {
+    let bundle_path = PathBuf::from(bundle_path);
+    let options = options.clone();
+    let mut worker_info = plugin_info.clone();
+    let queried = run_with_timeout(timeout, move |watchdog| {
+        // Each worker loads its own handle on the bundle
+        watchdog.enter("load");
+        let result = ClapScanner::get_bundle(bundle_path).and_then(|(bundle, _)| {
+            ClapInfoHost::new(bundle)
+                .with_watchdog(watchdog.clone())
+                .with_options(options)
+                .query_extensions(index, &mut worker_info)
+        });
+        (worker_info, result)
+    });
+
+    let result = match queried {
+        Ok((worker_info, result)) => {
+            *plugin_info = worker_info;
+            result
+        }
+        Err(timed_out) => Err(ClapInfoHostError::from(timed_out)),
+    };
+    if let Err(err) = &result {
+        plugin_info.set_error(err);
+    }
+    result
+}
+
+// Scan a bundle in a child process when the clap-info executable is given,
+// or in this process otherwise.
+fn scan_bundle(
+    clap_path: &Path,
+    clap_info_exe: Option<&Path>,
+    timeout: Option<Duration>,
+) -> ScanEntry {
+    if let Some(clap_info_exe) = clap_info_exe {
+        return ClapScanner::scan_bundle_out_of_process(clap_info_exe, clap_path, timeout);
+    }
+
+    let path = clap_path.display().to_string();
+    let bundle_path = path.clone();
+    let clap_path = clap_path.to_path_buf();
+    let info = run_with_timeout(timeout, move |watchdog| {
+        watchdog.enter("load");
+        let (bundle, bundle_file) = ClapScanner::get_bundle(clap_path)?;
+        InfoBundle::new(bundle_path, &bundle, Some(bundle_file))
+    });
+    match info {
+        Ok(Ok(info)) => ScanEntry::Bundle(info),
+        Ok(Err(err)) => ScanEntry::Failure(ScanFailure::from_error(path, &err)),
+        Err(timed_out) => ScanEntry::Failure(ScanFailure::timed_out(path, &timed_out)),
+    }
+}
@@ -0,0 +1,231 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+use clack_host::process::PluginAudioConfiguration;
+use serde_json::Value;
+
+pub const ACTIVATION_MATRIX_SAMPLE_RATES: [f64; 5] =
+    [44_100.0, 48_000.0, 88_200.0, 96_000.0, 192_000.0];
+
+/// The extensions whose values may depend on the activation settings: the only ones
+/// queried again at each sample rate of the activation matrix.
+pub const ACTIVATION_DEPENDENT_EXTENSIONS: [&str; 6] = [
+    "clap.latency",
+    "clap.tail",
+    "clap.audio-ports",
+    "clap.audio-ports-config",
+    "clap.note-ports",
+    "clap.voice-info",
+];
+
+#[derive(Debug, Clone, Copy)]
+pub struct ActivationSettings {
+    pub sample_rate: f64,
+    pub min_frames: u32,
+    pub max_frames: u32,
+}
+
+impl Default for ActivationSettings {
+    fn default() -> Self {
+        Self {
+            sample_rate: 48_000.0,
+            min_frames: 32,
+            max_frames: 4096,
+        }
+    }
+}
+
+impl ActivationSettings {
+    pub fn audio_configuration(&self) -> PluginAudioConfiguration {
+        PluginAudioConfiguration {
+            sample_rate: self.sample_rate,
+            min_frames_count: self.min_frames,
+            max_frames_count: self.max_frames,
+        }
+    }
+}
+
+/// The extension values that differ when the plugin is activated at different sample rates.
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct InfoActivationMatrix {
+    sample_rates: Vec<f64>,
+    /// Why querying the plugin failed, for each sample rate it failed at
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    errors: BTreeMap<String, String>,
+    changes: Vec<InfoActivationChange>,
+}
+
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct InfoActivationChange {
+    /// The changing value, e.g. `clap.latency/latency`
+    path: String,
+    /// The value at each sample rate (null when missing or when the query failed)
+    values: Vec<Option<Value>>,
+    /// Whether the value is proportional to the sample rate (e.g. a latency in samples)
+    scales_with_sample_rate: bool,
+}
+
+pub struct ActivationRun {
+    pub sample_rate: f64,
+    pub result: Result<HashMap<String, Value>, String>,
+}
+
+impl InfoActivationMatrix {
+    pub fn from_runs(runs: Vec<ActivationRun>) -> Self {
+        let sample_rates = runs.iter().map(|run| run.sample_rate).collect::<Vec<_>>();
+        let mut errors = BTreeMap::new();
+        let mut flattened = Vec::new();
+        for run in runs {
+            match run.result {
+                Ok(extensions) => {
+                    let mut values = BTreeMap::new();
+                    for (id, value) in extensions {
+                        flatten(id, value, &mut values);
+                    }
+                    flattened.push(Some(values));
+                }
+                Err(error) => {
+                    errors.insert(run.sample_rate.to_string(), error);
+                    flattened.push(None);
+                }
+            }
+        }
+
+        let paths = flattened
+            .iter()
+            .flatten()
+            .flat_map(|values| values.keys().cloned())
+            .collect::<BTreeSet<_>>();
+        let changes = paths
+            .into_iter()
+            .filter_map(|path| {
+                let values = flattened
+                    .iter()
+                    .map(|run| run.as_ref().and_then(|values| values.get(&path).cloned()))
+                    .collect::<Vec<_>>();
+                // Failed runs don't count as changes
+                let mut present = flattened
+                    .iter()
+                    .zip(&values)
+                    .filter(|(run, _)| run.is_some())
+                    .map(|(_, value)| value);
+                let first = present.next()?;
+                if present.all(|value| value == first) {
+                    return None;
+                }
+                Some(InfoActivationChange {
+                    scales_with_sample_rate: scales_with(&values, &sample_rates),
+                    path,
+                    values,
+                })
+            })
+            .collect();
+
+        Self {
+            sample_rates,
+            errors,
+            changes,
+        }
+    }
+}
+
+// Collect the leaves of a json value by their `/` separated path
+fn flatten(path: String, value: Value, leaves: &mut BTreeMap<String, Value>) {
+    match value {
+        Value::Object(object) => {
+            for (key, value) in object {
+                flatten(format!("{}/{}", path, key), value, leaves);
+            }
+        }
+        Value::Array(array) => {
+            for (index, value) in array.into_iter().enumerate() {
+                flatten(format!("{}/{}", path, index), value, leaves);
+            }
+        }
+        leaf => {
+            leaves.insert(path, leaf);
+        }
+    }
+}
+
+// Whether every value is a non-zero number with the same ratio to its sample rate (within 1%)
+fn scales_with(values: &[Option<Value>], sample_rates: &[f64]) -> bool {
+    let ratios = values
+        .iter()
+        .zip(sample_rates)
+        .filter_map(|(value, rate)| value.as_ref().map(|value| (value, rate)))
+        .map(|(value, rate)| {
+            value
+                .as_f64()
+                .filter(|value| *value != 0.0)
+                .map(|v| v / rate)
+        })
+        .collect::<Option<Vec<_>>>();
+    match ratios.as_deref() {
+        Some([first, rest @ ..]) if !rest.is_empty() => rest
+            .iter()
+            .all(|ratio| ((ratio - first) / first).abs() <= 0.01),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn run(sample_rate: f64, latency: Value) -> ActivationRun {
+        ActivationRun {
+            sample_rate,
+            result: Ok(HashMap::from([(
+                "clap.latency".to_string(),
+                json!({ "implemented": true, "latency": latency }),
+            )])),
+        }
+    }
+
+    #[test]
+    fn scales_with_proportional_values() {
+        let rates = [44_100.0, 48_000.0, 96_000.0];
+        let values = [Some(json!(441)), Some(json!(480)), Some(json!(960))];
+        assert!(scales_with(&values, &rates));
+
+        // Within 1%, and missing values are skipped
+        let values = [Some(json!(442)), None, Some(json!(960))];
+        assert!(scales_with(&values, &rates));
+    }
+
+    #[test]
+    fn does_not_scale_with_other_values() {
+        let rates = [44_100.0, 48_000.0, 96_000.0];
+        let constant = [Some(json!(64)), Some(json!(64)), Some(json!(64))];
+        assert!(!scales_with(&constant, &rates));
+        let zeros = [Some(json!(0)), Some(json!(0)), Some(json!(0))];
+        assert!(!scales_with(&zeros, &rates));
+        let texts = [Some(json!("a")), Some(json!("b")), Some(json!("c"))];
+        assert!(!scales_with(&texts, &rates));
+        // A single value proves nothing
+        assert!(!scales_with(&[Some(json!(441)), None, None], &rates));
+    }
+
+    #[test]
+    fn lists_the_values_that_change() {
+        let matrix = InfoActivationMatrix::from_runs(vec![
+            run(48_000.0, json!(480)),
+            ActivationRun {
+                sample_rate: 88_200.0,
+                result: Err("Failed to activate plugin".to_string()),
+            },
+            run(96_000.0, json!(960)),
+        ]);
+
+        assert_eq!(matrix.errors.keys().collect::<Vec<_>>(), ["88200"]);
+        assert_eq!(matrix.changes.len(), 1);
+        let change = &matrix.changes[0];
+        assert_eq!(change.path, "clap.latency/latency");
+        assert_eq!(change.values, [Some(json!(480)), None, Some(json!(960))]);
+        assert!(change.scales_with_sample_rate);
+    }
+}
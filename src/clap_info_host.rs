@@ -1,23 +1,104 @@
+use std::{
+    ffi::CStr,
+    path::Path,
+    sync::{Arc, Mutex},
+    time::Instant,
+};
+
+use clack_extensions::tail::PluginTail;
 use clack_host::{
-    bundle::PluginBundle,
+    bundle::{PluginBundle, PluginBundleError},
+    extensions::HostExtensions,
     factory::PluginFactory,
     host::{AudioProcessorHandler, HostHandlers, HostInfo, MainThreadHandler, SharedHandler},
     plugin::{PluginInstance, PluginInstanceError},
-    process::PluginAudioConfiguration,
+    prelude::{
+        AudioPortBuffer, AudioPortBufferType, AudioPorts, InputChannel, InputEvents, OutputEvents,
+    },
 };
 
 use crate::{
-    InfoAudioPortsConfigExtension, InfoGuiExtension, InfoLatencyExtension, InfoNoteNameExtension,
-    InfoParamsExtension, InfoPlugin, InfoStateExtension, InfoTailExtension,
+    ACTIVATION_DEPENDENT_EXTENSIONS, ACTIVATION_MATRIX_SAMPLE_RATES, ActivationRun,
+    ActivationSettings, HostCallbacks, InfoActivationMatrix, InfoAudioPort,
+    InfoAudioPortsConfigExtension, InfoAudioPortsExtension, InfoBundle, InfoGuiExtension,
+    InfoHostExtensions, InfoLatencyExtension, InfoLifecycle, InfoLifecycleStress,
+    InfoNoteNameExtension, InfoParamsExtension, InfoPlugin, InfoPresetDiscovery, InfoRender,
+    InfoRenderExtension, InfoStateExtension, InfoStressCycles, InfoTailExtension,
+    InfoVoiceInfoExtension, InputSignal, LogLevel, PluginLog, PluginMatcher, RenderSettings,
+    TimedOut, Watchdog, WavAudio,
 };
 
 #[derive(Debug, thiserror::Error)]
 pub enum ClapInfoHostError {
-    #[error("Failed to instantiate plugin")]
+    #[error("No CLAP bundle found at {0}")]
+    BundleNotFound(String),
+
+    #[error("Failed to load CLAP bundle {path}: {error}")]
+    BundleLoadError {
+        path: String,
+        error: PluginBundleError,
+    },
+
+    #[error("The CLAP bundle doesn't provide a plugin factory")]
+    MissingPluginFactory,
+
+    #[error("Invalid descriptor for plugin {index}: {reason}")]
+    InvalidDescriptor { index: usize, reason: &'static str },
+
+    #[error("Failed to instantiate plugin: {0}")]
     PluginInstanceError(PluginInstanceError),
 
+    #[error("Failed to activate plugin: {0}")]
+    ActivationError(PluginInstanceError),
+
     #[error("Invalid plugin index: {0}")]
     InvalidPluginIndex(usize),
+
+    #[error("No plugin matches {matcher}. Available plugin ids: {}", .available.join(", "))]
+    NoMatchingPlugin {
+        matcher: String,
+        available: Vec<String>,
+    },
+
+    #[error("The plugin has no audio output port to render")]
+    NoAudioOutput,
+
+    #[error("Failed to process audio: {0}")]
+    ProcessError(String),
+
+    #[error("Invalid render sample rate {0}: WAV files need a whole number of Hz")]
+    InvalidSampleRate(f64),
+
+    #[error("Failed to write WAV file {path}: {error}")]
+    WavWriteError { path: String, error: std::io::Error },
+
+    #[error(transparent)]
+    TimedOut(#[from] TimedOut),
+
+    #[error("Failed to serialize plugin information: {0}")]
+    SerializationError(#[from] serde_json::Error),
+}
+
+impl ClapInfoHostError {
+    /// A short, stable identifier of the error, used in the json output.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            ClapInfoHostError::BundleNotFound(_) => "bundle-not-found",
+            ClapInfoHostError::BundleLoadError { .. } => "bundle-load",
+            ClapInfoHostError::MissingPluginFactory => "missing-plugin-factory",
+            ClapInfoHostError::InvalidDescriptor { .. } => "invalid-descriptor",
+            ClapInfoHostError::PluginInstanceError(_) => "instantiation",
+            ClapInfoHostError::ActivationError(_) => "activation",
+            ClapInfoHostError::InvalidPluginIndex(_) => "invalid-plugin-index",
+            ClapInfoHostError::NoMatchingPlugin { .. } => "no-matching-plugin",
+            ClapInfoHostError::NoAudioOutput => "no-audio-output",
+            ClapInfoHostError::ProcessError(_) => "process",
+            ClapInfoHostError::InvalidSampleRate(_) => "invalid-sample-rate",
+            ClapInfoHostError::WavWriteError { .. } => "wav-write",
+            ClapInfoHostError::TimedOut(_) => "timed-out",
+            ClapInfoHostError::SerializationError(_) => "serialization",
+        }
+    }
 }
 
 impl From<PluginInstanceError> for ClapInfoHostError {
@@ -26,96 +107,734 @@ impl From<PluginInstanceError> for ClapInfoHostError {
     }
 }
 
+// Errors are reported as `{ "kind": ..., "message": ... }`
+impl serde::Serialize for ClapInfoHostError {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        use serde::ser::SerializeStruct;
+        let mut state = serializer.serialize_struct("ClapInfoHostError", 2)?;
+        state.serialize_field("kind", self.kind())?;
+        state.serialize_field("message", &self.to_string())?;
+        state.end()
+    }
+}
+
+/// Optional (and potentially slow) probes to run when querying plugin extensions,
+/// and what to do with the messages plugins log meanwhile.
+#[derive(Debug, Clone, Default)]
+pub struct QueryOptions {
+    pub params_roundtrip: bool,
+    pub params_tree: bool,
+    pub log_level: LogLevel,
+    pub log_to_stderr: bool,
+    pub activation: ActivationSettings,
+    pub activation_matrix: bool,
+    /// Repeat activate/deactivate and instantiate/destroy cycles this many times (0 to skip)
+    pub lifecycle_stress: usize,
+}
+
+// Which extensions to probe on an activated plugin instance
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Probes {
+    All,
+    // Only the ACTIVATION_DEPENDENT_EXTENSIONS, for the activation matrix
+    ActivationDependent,
+}
+
+impl Probes {
+    fn includes(self, extension_id: &str) -> bool {
+        self == Probes::All || ACTIVATION_DEPENDENT_EXTENSIONS.contains(&extension_id)
+    }
+}
+
+// What a plugin instance reports while being queried: its log messages, the host
+// callbacks it invokes and its lifecycle steps
+struct InstanceReport {
+    log: Arc<PluginLog>,
+    callbacks: Arc<HostCallbacks>,
+    lifecycle: InfoLifecycle,
+}
+
 // A minimal host implementation that just queries plugin extensions
 pub struct ClapInfoHost {
     bundle: PluginBundle,
+    watchdog: Watchdog,
+    options: QueryOptions,
 }
 
 impl ClapInfoHost {
     pub fn new(bundle: PluginBundle) -> Self {
-        Self { bundle }
+        Self {
+            bundle,
+            watchdog: Watchdog::new(),
+            options: QueryOptions::default(),
+        }
+    }
+
+    pub fn with_options(mut self, options: QueryOptions) -> Self {
+        self.options = options;
+        self
     }
 
+    /// Report every lifecycle phase and extension probe to the given watchdog,
+    /// so a hanging plugin call can be named (see [`crate::run_with_timeout`]).
+    pub fn with_watchdog(mut self, watchdog: Watchdog) -> Self {
+        self.watchdog = watchdog;
+        self
+    }
+
+    fn host_info() -> HostInfo {
+        HostInfo::new(
+            "clap-info-rs",
+            "danigb",
+            "github.com/danigb/clap-info-rs",
+            "0.1.0",
+        )
+        .expect("Static &str props never fail")
+    }
+
+    /// Query the extensions of every plugin in the bundle, one after another.
+    /// A failing plugin doesn't stop the others: its error is attached to its entry instead.
+    pub fn query_all_extensions(&mut self, bundle_info: &mut InfoBundle) {
+        for index in 0..bundle_info.plugin_count() {
+            let Ok(plugin_info) = bundle_info.get_plugin_mut(index) else {
+                continue;
+            };
+            if let Err(err) = self.query_extensions(index, plugin_info) {
+                plugin_info.set_error(&err);
+            }
+        }
+    }
+
+    /// Returns the factory indices of the plugins selected by `matcher`, or an error
+    /// listing the available plugin ids when none matches.
+    pub fn find_plugins(&self, matcher: &PluginMatcher) -> Result<Vec<usize>, ClapInfoHostError> {
+        let factory = self
+            .bundle
+            .get_factory::<PluginFactory<'_>>()
+            .ok_or(ClapInfoHostError::MissingPluginFactory)?;
+        let to_string = |s: Option<&CStr>| s.map(|s| s.to_string_lossy().to_string());
+
+        let mut indices = Vec::new();
+        let mut available = Vec::new();
+        for (index, descriptor) in factory.plugin_descriptors().enumerate() {
+            let id = to_string(descriptor.id()).unwrap_or_default();
+            let name = to_string(descriptor.name()).unwrap_or_default();
+            if matcher.matches(&id, &name) {
+                indices.push(index);
+            }
+            available.push(id);
+        }
+
+        if indices.is_empty() {
+            return Err(ClapInfoHostError::NoMatchingPlugin {
+                matcher: matcher.to_string(),
+                available,
+            });
+        }
+        Ok(indices)
+    }
+
+    /// Query the extensions of every plugin selected by `matcher`, attaching the
+    /// error of a failing plugin to its entry like [`ClapInfoHost::query_all_extensions`].
+    pub fn query_matching_extensions(
+        &mut self,
+        matcher: &PluginMatcher,
+        bundle_info: &mut InfoBundle,
+    ) -> Result<(), ClapInfoHostError> {
+        for index in self.find_plugins(matcher)? {
+            let plugin_info = bundle_info.get_plugin_mut(index)?;
+            if let Err(err) = self.query_extensions(index, plugin_info) {
+                plugin_info.set_error(&err);
+            }
+        }
+        Ok(())
+    }
+
+    // Start a new phase, first calling the plugin's on_main_thread if it asked for it
+    fn enter_phase(&self, plugin: &mut PluginInstance<Self>, phase: &'static str) {
+        if plugin.access_shared_handler(|sh| sh.callbacks.take_callback_request()) {
+            self.watchdog.enter("on-main-thread");
+            plugin.call_on_main_thread_callback();
+        }
+        self.watchdog.enter(phase);
+    }
+
+    /// Index the presets of every provider of the bundle's preset-discovery factory,
+    /// if it has one.
+    pub fn query_preset_discovery(&mut self, bundle_info: &mut InfoBundle) {
+        self.watchdog.enter("preset-discovery");
+        if let Some(preset_discovery) =
+            InfoPresetDiscovery::from_bundle(&self.bundle, &Self::host_info(), &self.watchdog)
+        {
+            bundle_info.set_preset_discovery(preset_discovery);
+        }
+    }
+
+    /// Same as [`ClapInfoHost::query_extensions`], selecting the plugin by its id.
+    pub fn query_extensions_by_id(
+        &mut self,
+        plugin_id: &str,
+        bundle_info: &mut InfoBundle,
+    ) -> Result<(), ClapInfoHostError> {
+        let index = self.find_plugins(&PluginMatcher::Id(plugin_id.to_string()))?[0];
+        self.query_extensions(index, bundle_info.get_plugin_mut(index)?)
+    }
+
+    /// Instantiate and activate the plugin at `index` on the current thread (which
+    /// becomes the plugin's main thread), probe its extensions, then deactivate and
+    /// destroy it. The lifecycle steps, the messages the plugin logs, the host callbacks
+    /// it invokes and its threading violations are added to `plugin_info`, even when
+    /// it fails.
     pub fn query_extensions(
         &mut self,
         index: usize,
         plugin_info: &mut InfoPlugin,
     ) -> Result<(), ClapInfoHostError> {
-        let factory = self.bundle.get_factory::<PluginFactory<'_>>().unwrap();
-        let plugin_id = factory
+        self.query_activated_extensions(index, plugin_info, self.options.activation, Probes::All)?;
+        if self.options.activation_matrix {
+            let activation_matrix = self.query_activation_matrix(index, plugin_info);
+            plugin_info.set_activation_matrix(activation_matrix);
+        }
+        if self.options.lifecycle_stress > 0 {
+            let lifecycle_stress = self.stress_lifecycle(index, self.options.lifecycle_stress)?;
+            plugin_info.set_lifecycle_stress(lifecycle_stress);
+        }
+        Ok(())
+    }
+
+    // Query the activation dependent extensions again at each sample rate of the
+    // matrix, on a copy of `plugin_info`, and compare the results.
+    fn query_activation_matrix(
+        &self,
+        index: usize,
+        plugin_info: &InfoPlugin,
+    ) -> InfoActivationMatrix {
+        let runs = ACTIVATION_MATRIX_SAMPLE_RATES
+            .into_iter()
+            .map(|sample_rate| {
+                let activation = ActivationSettings {
+                    sample_rate,
+                    ..self.options.activation
+                };
+                let mut run_info = plugin_info.clone();
+                let result = self
+                    .query_activated_extensions(
+                        index,
+                        &mut run_info,
+                        activation,
+                        Probes::ActivationDependent,
+                    )
+                    .map(|()| {
+                        run_info
+                            .extensions()
+                            .into_iter()
+                            .flatten()
+                            .filter(|(id, _)| Probes::ActivationDependent.includes(id))
+                            .map(|(id, value)| (id.clone(), value.clone()))
+                            .collect()
+                    })
+                    .map_err(|err| err.to_string());
+                ActivationRun {
+                    sample_rate,
+                    result,
+                }
+            })
+            .collect();
+        InfoActivationMatrix::from_runs(runs)
+    }
+
+    fn query_activated_extensions(
+        &self,
+        index: usize,
+        plugin_info: &mut InfoPlugin,
+        activation: ActivationSettings,
+        probes: Probes,
+    ) -> Result<(), ClapInfoHostError> {
+        let mut report = InstanceReport {
+            log: Arc::new(PluginLog::new(
+                self.watchdog.clone(),
+                self.options.log_level,
+                self.options.log_to_stderr,
+            )),
+            callbacks: Arc::new(HostCallbacks::new(self.watchdog.clone())),
+            lifecycle: InfoLifecycle::default(),
+        };
+        let result =
+            self.query_instance_extensions(index, plugin_info, activation, probes, &mut report);
+        plugin_info.set_lifecycle(report.lifecycle);
+        plugin_info.set_log(report.log.messages());
+        plugin_info.set_host_requests(report.callbacks.requests());
+        plugin_info.set_thread_violations(report.callbacks.violations());
+        result
+    }
+
+    fn plugin_id(&self, index: usize) -> Result<&CStr, ClapInfoHostError> {
+        let factory = self
+            .bundle
+            .get_factory::<PluginFactory<'_>>()
+            .ok_or(ClapInfoHostError::MissingPluginFactory)?;
+        factory
             .plugin_descriptor(index as u32)
             .ok_or(ClapInfoHostError::InvalidPluginIndex(index))?
             .id()
-            .expect("Failed to get plugin id");
-
-        let host_info = HostInfo::new(
-            "clap-info-rs",
-            "danigb",
-            "github.com/danigb/clap-info-rs",
-            "0.1.0",
-        )
-        .expect("Static &str props never fail");
+            .ok_or(ClapInfoHostError::InvalidDescriptor {
+                index,
+                reason: "missing plugin id",
+            })
+    }
 
-        let mut plugin: PluginInstance<Self> = PluginInstance::new(
-            |_| ClapInfoSharedHandler::default(),
+    fn new_instance(
+        &self,
+        plugin_id: &CStr,
+        log: &Arc<PluginLog>,
+        callbacks: &Arc<HostCallbacks>,
+    ) -> Result<PluginInstance<Self>, PluginInstanceError> {
+        PluginInstance::new(
+            |_| ClapInfoSharedHandler::new(log.clone(), callbacks.clone()),
             |sh: &ClapInfoSharedHandler| ClapInfoMainThreadHandler { sh },
             &self.bundle,
             plugin_id,
-            &host_info,
-        )?;
+            &Self::host_info(),
+        )
+    }
+
+    fn query_instance_extensions(
+        &self,
+        index: usize,
+        plugin_info: &mut InfoPlugin,
+        activation: ActivationSettings,
+        probes: Probes,
+        report: &mut InstanceReport,
+    ) -> Result<(), ClapInfoHostError> {
+        let InstanceReport {
+            log,
+            callbacks,
+            lifecycle,
+        } = report;
+        let plugin_id = self.plugin_id(index)?;
+
+        self.watchdog.enter("instantiate");
+        let mut plugin = lifecycle.run("init", || self.new_instance(plugin_id, log, callbacks))?;
 
         // We need to activate the processor to obtain some data (like latency)
-        let _stopped_audio_processor = plugin.activate(
-            |sh, _| ClipInfoAudioProcessor { sh },
-            PluginAudioConfiguration {
-                sample_rate: 48_000.0,
-                min_frames_count: 32,
-                max_frames_count: 4096,
-            },
-        );
+        self.enter_phase(&mut plugin, "activate");
+        let activated = lifecycle.run("activate", || {
+            plugin.activate(
+                |sh, _| ClipInfoAudioProcessor { sh },
+                activation.audio_configuration(),
+            )
+        });
+        let stopped_audio_processor = match activated {
+            Ok(stopped_audio_processor) => stopped_audio_processor,
+            Err(err) => {
+                self.destroy(plugin, lifecycle);
+                return Err(ClapInfoHostError::ActivationError(err));
+            }
+        };
+
+        // Some extensions (like tail) can only be queried from the audio thread while
+        // processing, so start the processor on a dedicated thread
+        let tail = plugin.plugin_handle().get_extension::<PluginTail>();
+        self.enter_phase(&mut plugin, "start-processing");
+        let watchdog = &self.watchdog;
+        let audio_lifecycle = &mut *lifecycle;
+        let (stopped_audio_processor, tail_extension) = std::thread::scope(|scope| {
+            scope
+                .spawn(move || {
+                    crate::host_callbacks::enter_audio_thread();
+                    let started_at = Instant::now();
+                    match stopped_audio_processor.start_processing() {
+                        Ok(mut started_audio_processor) => {
+                            audio_lifecycle.record("start-processing", started_at.elapsed(), None);
+                            watchdog.enter("clap.tail");
+                            let tail_extension = InfoTailExtension::from_processor(
+                                tail,
+                                Some(&mut started_audio_processor.plugin_handle()),
+                            );
+                            watchdog.enter("stop-processing");
+                            let started_at = Instant::now();
+                            let stopped_audio_processor = started_audio_processor.stop_processing();
+                            audio_lifecycle.record("stop-processing", started_at.elapsed(), None);
+                            (stopped_audio_processor, tail_extension)
+                        }
+                        Err(err) => {
+                            audio_lifecycle.record(
+                                "start-processing",
+                                started_at.elapsed(),
+                                Some(err.to_string()),
+                            );
+                            (
+                                err.into_stopped_processor(),
+                                InfoTailExtension::from_processor(tail, None),
+                            )
+                        }
+                    }
+                })
+                .join()
+                .unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+        });
+
+        // Keep going on failure: the instance must be torn down either way
+        let probed = self.probe_extensions(&mut plugin, plugin_info, probes, tail_extension);
+
+        let requested_extensions =
+            plugin.access_shared_handler(|sh| sh.requested_extensions.lock().unwrap().clone());
+        plugin_info.set_host_extensions(InfoHostExtensions::from_requests(requested_extensions));
 
-        let mut mt_handle = plugin.plugin_handle();
+        self.enter_phase(&mut plugin, "deactivate");
+        lifecycle.run_catching_panic("deactivate", || plugin.deactivate(stopped_audio_processor));
 
-        let params_info = InfoParamsExtension::from_plugin(&mut mt_handle);
-        plugin_info.add_extension("clap.params", params_info);
+        self.destroy(plugin, lifecycle);
+        probed
+    }
+
+    // Probe the extensions of an activated instance. The tail extension is probed
+    // beforehand, on the audio thread.
+    fn probe_extensions(
+        &self,
+        plugin: &mut PluginInstance<Self>,
+        plugin_info: &mut InfoPlugin,
+        probes: Probes,
+        tail_extension: InfoTailExtension,
+    ) -> Result<(), ClapInfoHostError> {
+        if probes.includes("clap.params") {
+            self.enter_phase(plugin, "clap.params");
+            let params_info =
+                InfoParamsExtension::from_plugin(&mut plugin.plugin_handle(), &self.options);
+            plugin_info.add_extension("clap.params", params_info)?;
+        }
 
-        let audio_ports = crate::info_ports::InfoAudioPortsExtension::from_plugin(&mut mt_handle);
-        plugin_info.add_extension("clap.audio-ports", audio_ports);
+        if probes.includes("clap.audio-ports") {
+            self.enter_phase(plugin, "clap.audio-ports");
+            let audio_ports = crate::info_ports::InfoAudioPortsExtension::from_plugin(
+                &mut plugin.plugin_handle(),
+            );
+            plugin_info.add_extension("clap.audio-ports", audio_ports)?;
+        }
 
-        let audio_ports_config =
-            crate::info_ports::InfoAudioPortsConfigs::from_plugin(&mut mt_handle);
-        plugin_info.add_extension("clap.audio-ports-config", audio_ports_config);
+        if probes.includes("clap.audio-ports-config") {
+            self.enter_phase(plugin, "clap.audio-ports-config");
+            let audio_ports_config =
+                crate::info_ports::InfoAudioPortsConfigs::from_plugin(&mut plugin.plugin_handle());
+            plugin_info.add_extension("clap.audio-ports-config", audio_ports_config)?;
+        }
 
-        let note_ports = crate::info_ports::InfoNotePorts::from_plugin(&mut mt_handle);
-        plugin_info.add_extension("clap.note-ports", note_ports);
+        if probes.includes("clap.note-ports") {
+            self.enter_phase(plugin, "clap.note-ports");
+            let note_ports =
+                crate::info_ports::InfoNotePorts::from_plugin(&mut plugin.plugin_handle());
+            plugin_info.add_extension("clap.note-ports", note_ports)?;
+        }
 
-        let latency_extension = InfoLatencyExtension::from_plugin(&mut mt_handle);
-        plugin_info.add_extension("clap.latency", latency_extension);
+        if probes.includes("clap.latency") {
+            self.enter_phase(plugin, "clap.latency");
+            let latency_extension = InfoLatencyExtension::from_plugin(&mut plugin.plugin_handle());
+            plugin_info.add_extension("clap.latency", latency_extension)?;
+        }
 
-        let tail_extension = InfoTailExtension::from_plugin(&mut mt_handle);
-        plugin_info.add_extension("clap.tail", tail_extension);
+        if probes.includes("clap.tail") {
+            plugin_info.add_extension("clap.tail", tail_extension)?;
+        }
 
-        let gui_extension = InfoGuiExtension::from_plugin(&mut mt_handle);
-        plugin_info.add_extension("clap.gui", gui_extension);
+        if probes.includes("clap.gui") {
+            self.enter_phase(plugin, "clap.gui");
+            let gui_extension = InfoGuiExtension::from_plugin(&mut plugin.plugin_handle());
+            plugin_info.add_extension("clap.gui", gui_extension)?;
+        }
 
-        let state_extension = InfoStateExtension::from_plugin(&mut mt_handle);
-        plugin_info.add_extension("clap.state", state_extension);
+        if probes.includes("clap.state") {
+            self.enter_phase(plugin, "clap.state");
+            let state_extension = InfoStateExtension::from_plugin(&mut plugin.plugin_handle());
+            plugin_info.add_extension("clap.state", state_extension)?;
+        }
 
-        let note_name_extension = InfoNoteNameExtension::from_plugin(&mut mt_handle);
-        plugin_info.add_extension("clap.note-name", note_name_extension);
+        if probes.includes("clap.note-name") {
+            self.enter_phase(plugin, "clap.note-name");
+            let note_name_extension =
+                InfoNoteNameExtension::from_plugin(&mut plugin.plugin_handle());
+            plugin_info.add_extension("clap.note-name", note_name_extension)?;
+        }
 
-        let audio_ports_config_extension =
-            InfoAudioPortsConfigExtension::from_plugin(&mut mt_handle);
-        plugin_info.add_extension("clap.audio-ports-config", audio_ports_config_extension);
+        if probes.includes("clap.audio-ports-config") {
+            self.enter_phase(plugin, "clap.audio-ports-config");
+            let audio_ports_config_extension =
+                InfoAudioPortsConfigExtension::from_plugin(&mut plugin.plugin_handle());
+            plugin_info.add_extension("clap.audio-ports-config", audio_ports_config_extension)?;
+        }
+
+        if probes.includes("clap.voice-info") {
+            self.enter_phase(plugin, "clap.voice-info");
+            let voice_info_extension =
+                InfoVoiceInfoExtension::from_plugin(&mut plugin.plugin_handle());
+            plugin_info.add_extension("clap.voice-info", voice_info_extension)?;
+        }
+
+        if probes.includes("clap.render") {
+            self.enter_phase(plugin, "clap.render");
+            let render_extension = InfoRenderExtension::from_plugin(&mut plugin.plugin_handle());
+            plugin_info.add_extension("clap.render", render_extension)?;
+        }
 
         Ok(())
     }
+
+    // Destroy the plugin instance (by dropping it), after a last on_main_thread call
+    // if the plugin asked for one.
+    fn destroy(&self, mut plugin: PluginInstance<Self>, lifecycle: &mut InfoLifecycle) {
+        self.enter_phase(&mut plugin, "destroy");
+        lifecycle.run_catching_panic("destroy", || drop(plugin));
+    }
+
+    /// Instantiate and activate the plugin at `index`, then feed the input signal to its
+    /// main input port (and silence to the others) for `settings.seconds`, block by block
+    /// on a dedicated audio thread. The buffers follow the layout reported by
+    /// clap.audio-ports, and each output port is written to `<plugin id>.out<port>.wav`
+    /// in `out_dir`. The sample rate must be a whole number of Hz.
+    pub fn render(
+        &mut self,
+        index: usize,
+        settings: &RenderSettings,
+        out_dir: &Path,
+    ) -> Result<InfoRender, ClapInfoHostError> {
+        if !settings.has_whole_sample_rate() {
+            return Err(ClapInfoHostError::InvalidSampleRate(settings.sample_rate));
+        }
+        let plugin_id = self.plugin_id(index)?;
+        let log = Arc::new(PluginLog::new(
+            self.watchdog.clone(),
+            self.options.log_level,
+            self.options.log_to_stderr,
+        ));
+        let callbacks = Arc::new(HostCallbacks::new(self.watchdog.clone()));
+        let mut lifecycle = InfoLifecycle::default();
+
+        self.watchdog.enter("instantiate");
+        let mut plugin =
+            lifecycle.run("init", || self.new_instance(plugin_id, &log, &callbacks))?;
+
+        self.enter_phase(&mut plugin, "clap.audio-ports");
+        let audio_ports = InfoAudioPortsExtension::from_plugin(&mut plugin.plugin_handle());
+        let frames = settings.frames();
+        let main_input = audio_ports
+            .input_ports()
+            .iter()
+            .position(InfoAudioPort::is_main)
+            .unwrap_or(0);
+        let mut inputs = audio_ports
+            .input_ports()
+            .iter()
+            .enumerate()
+            .map(|(port_index, port)| {
+                let signal = if port_index == main_input {
+                    &settings.input
+                } else {
+                    &InputSignal::Silence
+                };
+                signal.channels(port.channel_count() as usize, frames, settings.sample_rate)
+            })
+            .collect::<Vec<_>>();
+        let mut outputs = audio_ports
+            .output_ports()
+            .iter()
+            .map(|port| vec![vec![0.0_f32; frames]; port.channel_count() as usize])
+            .collect::<Vec<_>>();
+        if outputs.iter().all(Vec::is_empty) {
+            self.destroy(plugin, &mut lifecycle);
+            return Err(ClapInfoHostError::NoAudioOutput);
+        }
+
+        self.enter_phase(&mut plugin, "activate");
+        let activated = lifecycle.run("activate", || {
+            plugin.activate(
+                |sh, _| ClipInfoAudioProcessor { sh },
+                settings.activation().audio_configuration(),
+            )
+        });
+        let stopped_audio_processor = match activated {
+            Ok(stopped_audio_processor) => stopped_audio_processor,
+            Err(err) => {
+                self.destroy(plugin, &mut lifecycle);
+                return Err(ClapInfoHostError::ActivationError(err));
+            }
+        };
+
+        self.enter_phase(&mut plugin, "start-processing");
+        let watchdog = &self.watchdog;
+        let audio_lifecycle = &mut lifecycle;
+        let (input_buffers, output_buffers) = (&mut inputs, &mut outputs);
+        let block_size = settings.block_size as usize;
+        let (stopped_audio_processor, processed) = std::thread::scope(|scope| {
+            scope
+                .spawn(move || {
+                    crate::host_callbacks::enter_audio_thread();
+                    let started_at = Instant::now();
+                    let mut started_audio_processor =
+                        match stopped_audio_processor.start_processing() {
+                            Ok(started_audio_processor) => started_audio_processor,
+                            Err(err) => {
+                                let error = err.to_string();
+                                audio_lifecycle.record(
+                                    "start-processing",
+                                    started_at.elapsed(),
+                                    Some(error.clone()),
+                                );
+                                return (err.into_stopped_processor(), Err(error));
+                            }
+                        };
+                    audio_lifecycle.record("start-processing", started_at.elapsed(), None);
+
+                    let processed = audio_lifecycle.run("process", || {
+                        let mut input_ports = AudioPorts::with_capacity(
+                            input_buffers.iter().map(Vec::len).sum(),
+                            input_buffers.len(),
+                        );
+                        let mut output_ports = AudioPorts::with_capacity(
+                            output_buffers.iter().map(Vec::len).sum(),
+                            output_buffers.len(),
+                        );
+                        let input_events = InputEvents::empty();
+                        let mut output_events = OutputEvents::void();
+                        for start in (0..frames).step_by(block_size) {
+                            // Each block gets the full timeout
+                            watchdog.enter("process");
+                            let end = (start + block_size).min(frames);
+                            let input_audio = input_ports.with_input_buffers(
+                                input_buffers.iter_mut().map(|port| AudioPortBuffer {
+                                    latency: 0,
+                                    channels: AudioPortBufferType::f32_input_only(
+                                        port.iter_mut().map(|channel| {
+                                            InputChannel::variable(&mut channel[start..end])
+                                        }),
+                                    ),
+                                }),
+                            );
+                            let mut output_audio = output_ports.with_output_buffers(
+                                output_buffers.iter_mut().map(|port| AudioPortBuffer {
+                                    latency: 0,
+                                    channels: AudioPortBufferType::f32_output_only(
+                                        port.iter_mut().map(|channel| &mut channel[start..end]),
+                                    ),
+                                }),
+                            );
+                            started_audio_processor.process(
+                                &input_audio,
+                                &mut output_audio,
+                                &input_events,
+                                &mut output_events,
+                                Some(start as u64),
+                                None,
+                            )?;
+                        }
+                        Ok::<_, PluginInstanceError>(())
+                    });
+
+                    watchdog.enter("stop-processing");
+                    let started_at = Instant::now();
+                    let stopped_audio_processor = started_audio_processor.stop_processing();
+                    audio_lifecycle.record("stop-processing", started_at.elapsed(), None);
+                    (
+                        stopped_audio_processor,
+                        processed.map_err(|err| err.to_string()),
+                    )
+                })
+                .join()
+                .unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+        });
+
+        self.enter_phase(&mut plugin, "deactivate");
+        lifecycle.run_catching_panic("deactivate", || plugin.deactivate(stopped_audio_processor));
+        self.destroy(plugin, &mut lifecycle);
+        processed.map_err(ClapInfoHostError::ProcessError)?;
+
+        self.watchdog.enter("write-wav");
+        let plugin_id = plugin_id.to_string_lossy().to_string();
+        // Keep the plugin id usable as a file name
+        let file_stem = plugin_id
+            .chars()
+            .map(|c| match c {
+                'a'..='z' | 'A'..='Z' | '0'..='9' | '.' | '-' | '_' => c,
+                _ => '_',
+            })
+            .collect::<String>();
+        let mut render = InfoRender::new(plugin_id, settings, lifecycle, log.messages());
+        for (port_index, (port, channels)) in
+            audio_ports.output_ports().iter().zip(outputs).enumerate()
+        {
+            let path = (!channels.is_empty())
+                .then(|| out_dir.join(format!("{}.out{}.wav", file_stem, port_index)));
+            render.add_output_port(port.name().to_string(), &channels, path.as_deref());
+            if let Some(path) = path {
+                let wav = WavAudio {
+                    sample_rate: settings.sample_rate as u32,
+                    channels,
+                };
+                wav.write(&path)
+                    .map_err(|error| ClapInfoHostError::WavWriteError {
+                        path: path.display().to_string(),
+                        error,
+                    })?;
+            }
+        }
+        Ok(render)
+    }
+
+    // Repeat activate -> deactivate cycles on a single instance, then
+    // instantiate -> destroy cycles, `cycles` times each.
+    fn stress_lifecycle(
+        &self,
+        index: usize,
+        cycles: usize,
+    ) -> Result<InfoLifecycleStress, ClapInfoHostError> {
+        let plugin_id = self.plugin_id(index)?;
+        let new_plugin = || {
+            let log = Arc::new(PluginLog::new(
+                self.watchdog.clone(),
+                self.options.log_level,
+                self.options.log_to_stderr,
+            ));
+            let callbacks = Arc::new(HostCallbacks::new(self.watchdog.clone()));
+            self.new_instance(plugin_id, &log, &callbacks)
+        };
+
+        // Each cycle gets the full timeout
+        self.watchdog.enter("stress-activate-deactivate");
+        let activate_deactivate = match new_plugin() {
+            Ok(mut plugin) => InfoStressCycles::run(cycles, || {
+                self.watchdog.enter("stress-activate-deactivate");
+                let stopped_audio_processor = plugin.activate(
+                    |sh, _| ClipInfoAudioProcessor { sh },
+                    self.options.activation.audio_configuration(),
+                )?;
+                plugin.deactivate(stopped_audio_processor);
+                Ok::<_, PluginInstanceError>(())
+            }),
+            Err(err) => InfoStressCycles::not_started(ClapInfoHostError::from(err)),
+        };
+
+        let instantiate_destroy = InfoStressCycles::run(cycles, || {
+            self.watchdog.enter("stress-instantiate-destroy");
+            new_plugin().map(drop)
+        });
+
+        Ok(InfoLifecycleStress::new(
+            cycles,
+            activate_deactivate,
+            instantiate_destroy,
+        ))
+    }
 }
 
 impl HostHandlers for ClapInfoHost {
     type AudioProcessor<'a> = ClipInfoAudioProcessor<'a>;
     type Shared<'a> = ClapInfoSharedHandler;
     type MainThread<'a> = ClapInfoMainThreadHandler<'a>;
+
+    fn declare_extensions(builder: &mut HostExtensions<Self>, shared: &Self::Shared<'_>) {
+        Self::declare_host_extensions(builder, shared);
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -124,13 +843,36 @@ pub struct ClipInfoAudioProcessor<'a> {
 }
 impl<'a> AudioProcessorHandler<'a> for ClipInfoAudioProcessor<'a> {}
 
-#[derive(Debug, Clone, Default)]
-pub struct ClapInfoSharedHandler {}
+#[derive(Debug)]
+pub struct ClapInfoSharedHandler {
+    pub(crate) requested_extensions: Mutex<Vec<String>>,
+    pub(crate) log: Arc<PluginLog>,
+    pub(crate) callbacks: Arc<HostCallbacks>,
+}
+
+impl ClapInfoSharedHandler {
+    pub fn new(log: Arc<PluginLog>, callbacks: Arc<HostCallbacks>) -> Self {
+        Self {
+            requested_extensions: Mutex::new(Vec::new()),
+            log,
+            callbacks,
+        }
+    }
+}
 
 impl SharedHandler<'_> for ClapInfoSharedHandler {
-    fn request_restart(&self) {}
-    fn request_process(&self) {}
-    fn request_callback(&self) {}
+    fn request_restart(&self) {
+        self.callbacks.record("request_restart");
+    }
+
+    fn request_process(&self) {
+        self.callbacks.record("request_process");
+    }
+
+    // on_main_thread is called at the start of the next phase
+    fn request_callback(&self) {
+        self.callbacks.request_callback();
+    }
 }
 
 #[derive(Debug)]
@@ -1,8 +1,129 @@
-use std::{collections::HashMap, ffi::CStr, path::PathBuf};
+use std::{
+    collections::{HashMap, HashSet},
+    io::Read,
+    path::{Path, PathBuf},
+    process::{Command, ExitStatus, Stdio},
+    thread::JoinHandle,
+    time::{Duration, Instant},
+};
 
 use clack_host::bundle::PluginBundle;
 
-use crate::InfoBundle;
+use crate::{ClapInfoHostError, InfoBundle, TimedOut};
+
+pub const DEFAULT_MAX_DEPTH: usize = 8;
+
+pub(crate) const CLAP_PATH_ENV: &str = "CLAP_PATH";
+pub(crate) const DEFAULT_CHILD_TIMEOUT: Duration = Duration::from_secs(60);
+pub(crate) const TIMEOUT_FLAG: &str = "--timeout";
+
+// Extra time given to a child process on top of its own timeout, so it can report
+// which phase timed out before being killed
+const CHILD_TIMEOUT_GRACE: Duration = Duration::from_secs(2);
+
+// Makes `clap-info` print the bundle descriptors without instantiating any plugin
+pub(crate) const DESCRIPTORS_ONLY_FLAG: &str = "--descriptors-only";
+
+// How many trailing stderr lines of a failed child are kept
+const STDERR_TAIL_LINES: usize = 20;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SearchPathSource {
+    ClapPath,
+    User,
+    System,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct SearchPath {
+    path: String,
+    source: SearchPathSource,
+}
+
+impl SearchPath {
+    pub fn new(path: String, source: SearchPathSource) -> Self {
+        Self { path, source }
+    }
+
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    pub fn source(&self) -> SearchPathSource {
+        self.source
+    }
+}
+
+#[derive(serde::Serialize)]
+#[serde(untagged)]
+pub enum ScanEntry {
+    Bundle(InfoBundle),
+    Failure(ScanFailure),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ScanFailureKind {
+    SpawnFailed,
+    Crashed,
+    Exited,
+    Hung,
+    InvalidOutput,
+    LoadFailed,
+}
+
+#[derive(Debug, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct ScanFailure {
+    path: String,
+    failure: ScanFailureKind,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    exit_code: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    signal: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    timed_out_in: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<serde_json::Value>,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    stderr_tail: String,
+}
+
+impl ScanFailure {
+    pub fn new(path: String, failure: ScanFailureKind, stderr_tail: String) -> Self {
+        Self {
+            path,
+            failure,
+            exit_code: None,
+            signal: None,
+            timed_out_in: None,
+            error: None,
+            stderr_tail,
+        }
+    }
+
+    /// A bundle that couldn't be loaded or described in this process.
+    pub fn from_error(path: String, error: &ClapInfoHostError) -> Self {
+        Self {
+            error: serde_json::to_value(error).ok(),
+            ..Self::new(path, ScanFailureKind::LoadFailed, String::new())
+        }
+    }
+
+    /// A bundle that didn't finish a phase in time, see [`crate::run_with_timeout`].
+    pub fn timed_out(path: String, timed_out: &TimedOut) -> Self {
+        Self {
+            timed_out_in: Some(timed_out.timed_out_in().to_string()),
+            ..Self::new(path, ScanFailureKind::Hung, String::new())
+        }
+    }
+
+    pub fn failure(&self) -> ScanFailureKind {
+        self.failure
+    }
+}
 
 pub struct ClapScanner;
 
@@ -12,76 +133,470 @@ impl ClapScanner {
     }
 
     pub fn installed_claps() -> Vec<PathBuf> {
+        Self::installed_claps_with_max_depth(DEFAULT_MAX_DEPTH)
+    }
+
+    /// Recursively search every search path for CLAP bundles, descending at most
+    /// `max_depth` directories below each search path.
+    ///
+    /// Directories reached twice (through symlinks or nested search paths) are only
+    /// walked again when reached with more depth left, and a bundle reachable through
+    /// several paths is only reported the first time it's found.
+    pub fn installed_claps_with_max_depth(max_depth: usize) -> Vec<PathBuf> {
         let search_paths = Self::get_search_paths();
         let mut claps = Vec::new();
+        let mut visited_dirs = HashMap::new();
+        let mut found_claps = HashSet::new();
 
-        for path in search_paths {
-            if let Ok(entries) = std::fs::read_dir(path) {
-                for entry in entries.flatten() {
-                    let path = entry.path();
-                    if path.extension().and_then(|s| s.to_str()) == Some("clap") {
-                        if cfg!(target_os = "macos") {
-                            if path.is_dir() {
-                                claps.push(path);
-                            }
-                        } else {
-                            if !path.is_dir() {
-                                claps.push(path);
-                            }
-                        }
+        for search_path in search_paths {
+            Self::find_claps(
+                Path::new(search_path.path()),
+                max_depth,
+                &mut visited_dirs,
+                &mut found_claps,
+                &mut claps,
+            );
+        }
+
+        claps
+    }
+
+    fn find_claps(
+        dir: &Path,
+        depth_left: usize,
+        visited_dirs: &mut HashMap<PathBuf, usize>,
+        found_claps: &mut HashSet<PathBuf>,
+        claps: &mut Vec<PathBuf>,
+    ) {
+        // Canonicalize to detect symlink loops and directories reached twice, keeping
+        // the depth each directory was walked with
+        let Ok(canonical_dir) = dir.canonicalize() else {
+            return;
+        };
+        if visited_dirs
+            .get(&canonical_dir)
+            .is_some_and(|&visited_depth| visited_depth >= depth_left)
+        {
+            return;
+        }
+        visited_dirs.insert(canonical_dir, depth_left);
+
+        let Ok(entries) = std::fs::read_dir(dir) else {
+            return;
+        };
+
+        let mut entries = entries.flatten().map(|e| e.path()).collect::<Vec<_>>();
+        entries.sort();
+
+        for path in entries {
+            if path.extension().and_then(|s| s.to_str()) == Some("clap") {
+                // On macOS bundles are directories, elsewhere they're plain files
+                let is_bundle = if cfg!(target_os = "macos") {
+                    path.is_dir()
+                } else {
+                    !path.is_dir()
+                };
+                if is_bundle {
+                    let canonical = path.canonicalize().unwrap_or_else(|_| path.clone());
+                    if found_claps.insert(canonical) {
+                        claps.push(path);
                     }
+                    continue;
                 }
             }
-        }
 
-        claps
+            if depth_left > 0 && path.is_dir() {
+                Self::find_claps(&path, depth_left - 1, visited_dirs, found_claps, claps);
+            }
+        }
     }
 
     // Try to get a bundle from a bundle path (potentially a directory with multiple files inside).
-    pub fn get_bundle(path: PathBuf) -> Option<(PluginBundle, PathBuf)> {
-        if path.is_dir() {
-            match std::fs::read_dir(path) {
-                Ok(dir) => dir.into_iter().find_map(|entry| {
-                    entry
-                        .ok()
-                        .map(|entry| Self::get_bundle(entry.path()))
-                        .flatten()
-                }),
+    // When no file inside a directory can be loaded, the first load error is returned.
+    pub fn get_bundle(path: PathBuf) -> Result<(PluginBundle, PathBuf), ClapInfoHostError> {
+        let not_found = || ClapInfoHostError::BundleNotFound(path.display().to_string());
 
-                Err(_) => None,
+        if path.is_dir() {
+            let dir = std::fs::read_dir(&path).map_err(|_| not_found())?;
+            let mut first_error = None;
+            for entry in dir.flatten() {
+                match Self::get_bundle(entry.path()) {
+                    Ok(bundle) => return Ok(bundle),
+                    Err(err) => {
+                        first_error.get_or_insert(err);
+                    }
+                }
             }
+            Err(first_error.unwrap_or_else(not_found))
         } else if path.is_file() {
-            unsafe { PluginBundle::load(&path) }
-                .ok()
-                .map(|bundle| (bundle, path))
+            match unsafe { PluginBundle::load(&path) } {
+                Ok(bundle) => Ok((bundle, path)),
+                Err(error) => Err(ClapInfoHostError::BundleLoadError {
+                    path: path.display().to_string(),
+                    error,
+                }),
+            }
         } else {
-            None
+            Err(not_found())
+        }
+    }
+
+    /// Scan a bundle by running `clap_info_exe` (the `clap-info` binary) on it in a
+    /// child process, so a plugin crashing or hanging while loading can't take down the
+    /// whole scan.
+    ///
+    /// The `timeout` is forwarded to the child so it can name the phase that timed out.
+    /// Without one, the child is killed after 60 seconds.
+    pub fn scan_bundle_out_of_process(
+        clap_info_exe: &Path,
+        clap_path: &Path,
+        timeout: Option<Duration>,
+    ) -> ScanEntry {
+        let path = clap_path.display().to_string();
+
+        let mut command = Command::new(clap_info_exe);
+        command.arg(DESCRIPTORS_ONLY_FLAG);
+        if let Some(timeout) = timeout {
+            command
+                .arg(TIMEOUT_FLAG)
+                .arg(timeout.as_millis().to_string());
+        }
+        let timeout = timeout
+            .map(|timeout| timeout + CHILD_TIMEOUT_GRACE)
+            .unwrap_or(DEFAULT_CHILD_TIMEOUT);
+
+        let mut child = match command
+            .arg(clap_path)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+        {
+            Ok(child) => child,
+            Err(err) => {
+                return ScanEntry::Failure(ScanFailure::new(
+                    path,
+                    ScanFailureKind::SpawnFailed,
+                    err.to_string(),
+                ));
+            }
+        };
+
+        // Drain the pipes in the background so the child never blocks on a full pipe
+        let stdout = Self::read_in_background(child.stdout.take());
+        let stderr = Self::read_in_background(child.stderr.take());
+
+        let started_at = Instant::now();
+        let status = loop {
+            match child.try_wait() {
+                Ok(Some(status)) => break Some(status),
+                Ok(None) if started_at.elapsed() < timeout => {
+                    std::thread::sleep(Duration::from_millis(10));
+                }
+                _ => {
+                    let _ = child.kill();
+                    let _ = child.wait();
+                    break None;
+                }
+            }
+        };
+
+        let stdout = stdout.join().unwrap_or_default();
+        let stderr = stderr.join().unwrap_or_default();
+        let stderr_tail = Self::tail_lines(&stderr, STDERR_TAIL_LINES);
+
+        let Some(status) = status else {
+            return ScanEntry::Failure(ScanFailure::new(path, ScanFailureKind::Hung, stderr_tail));
+        };
+
+        // The child prints a `{ "action": ..., "result": ... }` object on success,
+        // and `{ "action": ..., "error": ... }` on failure
+        let mut output = serde_json::from_slice::<serde_json::Value>(&stdout).unwrap_or_default();
+        let result = output.get_mut("result").map(serde_json::Value::take);
+
+        if !status.success() {
+            // The child reports its error, or the phase that timed out, before exiting
+            let error = output.get_mut("error").map(serde_json::Value::take);
+            let timed_out_in = result
+                .as_ref()
+                .and_then(|result| result.get("timed-out-in"))
+                .and_then(|phase| phase.as_str());
+            if let Some(timed_out_in) = timed_out_in {
+                return ScanEntry::Failure(ScanFailure {
+                    exit_code: status.code(),
+                    timed_out_in: Some(timed_out_in.to_string()),
+                    error,
+                    ..ScanFailure::new(path, ScanFailureKind::Hung, stderr_tail)
+                });
+            }
+
+            let signal = Self::exit_signal(&status);
+            let failure = if signal.is_some() {
+                ScanFailureKind::Crashed
+            } else {
+                ScanFailureKind::Exited
+            };
+            return ScanEntry::Failure(ScanFailure {
+                exit_code: status.code(),
+                signal,
+                error,
+                ..ScanFailure::new(path, failure, stderr_tail)
+            });
+        }
+
+        result
+            .and_then(|result| serde_json::from_value::<InfoBundle>(result).ok())
+            .map(ScanEntry::Bundle)
+            .unwrap_or_else(|| {
+                ScanEntry::Failure(ScanFailure {
+                    exit_code: status.code(),
+                    ..ScanFailure::new(path, ScanFailureKind::InvalidOutput, stderr_tail)
+                })
+            })
+    }
+
+    fn read_in_background<R: Read + Send + 'static>(reader: Option<R>) -> JoinHandle<Vec<u8>> {
+        std::thread::spawn(move || {
+            let mut buffer = Vec::new();
+            if let Some(mut reader) = reader {
+                let _ = reader.read_to_end(&mut buffer);
+            }
+            buffer
+        })
+    }
+
+    fn tail_lines(output: &[u8], count: usize) -> String {
+        let output = String::from_utf8_lossy(output);
+        let lines = output.lines().collect::<Vec<_>>();
+        lines[lines.len().saturating_sub(count)..].join("\n")
+    }
+
+    #[cfg(unix)]
+    fn exit_signal(status: &ExitStatus) -> Option<i32> {
+        use std::os::unix::process::ExitStatusExt;
+        status.signal()
+    }
+
+    #[cfg(not(unix))]
+    fn exit_signal(_status: &ExitStatus) -> Option<i32> {
+        None
+    }
+
+    /// Returns the directories to search for CLAP bundles, in priority order:
+    ///
+    /// 1. every entry of `CLAP_PATH`, in the order they are listed
+    /// 2. the per-user default location
+    /// 3. the system-wide default locations
+    ///
+    /// Duplicated entries are only reported the first time they appear.
+    pub fn get_search_paths() -> Vec<SearchPath> {
+        let mut search_paths = Self::get_env_search_paths();
+
+        for default in Self::get_default_search_paths() {
+            if !search_paths.iter().any(|p| p.path == default.path) {
+                search_paths.push(default);
+            }
         }
+
+        search_paths
+    }
+
+    // Parse CLAP_PATH using the platform separator (':' on unix, ';' on windows)
+    // and expand tildes and environment variables (`$VAR`, and `%VAR%` on windows)
+    // on each entry.
+    fn get_env_search_paths() -> Vec<SearchPath> {
+        let mut search_paths: Vec<SearchPath> = Vec::new();
+
+        let Some(clap_path) = std::env::var_os(CLAP_PATH_ENV) else {
+            return search_paths;
+        };
+
+        for entry in std::env::split_paths(&clap_path) {
+            let mut entry = entry.to_string_lossy().to_string();
+            if entry.trim().is_empty() {
+                continue;
+            }
+            if cfg!(windows) {
+                entry = Self::expand_percent_vars(&entry, |name| std::env::var(name).ok());
+            }
+            let path = shellexpand::full(&entry)
+                .map(|p| p.to_string())
+                .unwrap_or_else(|_| shellexpand::tilde(&entry).to_string());
+
+            if !search_paths.iter().any(|p| p.path == path) {
+                search_paths.push(SearchPath::new(path, SearchPathSource::ClapPath));
+            }
+        }
+
+        search_paths
     }
 
-    pub fn get_search_paths() -> Vec<String> {
+    fn get_default_search_paths() -> Vec<SearchPath> {
         #[cfg(target_os = "linux")]
         {
             vec![
-                "/usr/lib/clap".to_string(),
-                shellexpand::tilde("~/.clap").to_string(),
+                SearchPath::new(
+                    shellexpand::tilde("~/.clap").to_string(),
+                    SearchPathSource::User,
+                ),
+                SearchPath::new("/usr/lib/clap".to_string(), SearchPathSource::System),
             ]
         }
 
         #[cfg(target_os = "macos")]
         {
             vec![
-                "/Library/Audio/Plug-Ins/CLAP".to_string(),
-                shellexpand::tilde("~/Library/Audio/Plug-Ins/CLAP").to_string(),
+                SearchPath::new(
+                    shellexpand::tilde("~/Library/Audio/Plug-Ins/CLAP").to_string(),
+                    SearchPathSource::User,
+                ),
+                SearchPath::new(
+                    "/Library/Audio/Plug-Ins/CLAP".to_string(),
+                    SearchPathSource::System,
+                ),
             ]
         }
-        #[cfg(target_os = "windows")]
+        #[cfg(windows)]
         {
-            vec![
-                "%COMMONPROGRAMFILES%\\CLAP".to_string(),
-                "%LOCALAPPDATA%\\Programs\\Common\\CLAP".to_string(),
-                shellexpand::tilde("~/Library/Audio/Plug-Ins/CLAP").to_string(),
+            // Locations whose environment variable isn't set are skipped
+            [
+                (
+                    "%LOCALAPPDATA%\\Programs\\Common\\CLAP",
+                    SearchPathSource::User,
+                ),
+                ("%COMMONPROGRAMFILES%\\CLAP", SearchPathSource::System),
             ]
+            .into_iter()
+            .map(|(path, source)| {
+                let path = Self::expand_percent_vars(path, |name| std::env::var(name).ok());
+                SearchPath::new(path, source)
+            })
+            .filter(|search_path| !search_path.path().contains('%'))
+            .collect()
+        }
+    }
+
+    // Expand the `%VAR%` references of a windows path, leaving unknown variables as is
+    fn expand_percent_vars(path: &str, lookup: impl Fn(&str) -> Option<String>) -> String {
+        let mut expanded = String::new();
+        let mut rest = path;
+        while let Some(start) = rest.find('%') {
+            let Some(len) = rest[start + 1..].find('%') else {
+                break;
+            };
+            let name = &rest[start + 1..start + 1 + len];
+            expanded.push_str(&rest[..start]);
+            match lookup(name).filter(|_| !name.is_empty()) {
+                Some(value) => expanded.push_str(&value),
+                None => expanded.push_str(&rest[start..start + len + 2]),
+            }
+            rest = &rest[start + len + 2..];
         }
+        expanded.push_str(rest);
+        expanded
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::temp_dir;
+
+    fn create_bundle(path: &Path) {
+        if cfg!(target_os = "macos") {
+            std::fs::create_dir_all(path).unwrap();
+        } else {
+            std::fs::write(path, b"").unwrap();
+        }
+    }
+
+    #[test]
+    fn walks_again_directories_reached_with_more_depth_left() {
+        let root = temp_dir("depth");
+        let inner = root.join("outer").join("inner");
+        std::fs::create_dir_all(inner.join("vendor")).unwrap();
+        create_bundle(&inner.join("vendor").join("plugin.clap"));
+
+        let mut visited_dirs = HashMap::new();
+        let mut found_claps = HashSet::new();
+        let mut claps = Vec::new();
+        // Reaches `inner` at the depth limit...
+        let outer = root.join("outer");
+        ClapScanner::find_claps(&outer, 1, &mut visited_dirs, &mut found_claps, &mut claps);
+        assert!(claps.is_empty());
+        // ...so a search path starting there must still walk it
+        ClapScanner::find_claps(&inner, 1, &mut visited_dirs, &mut found_claps, &mut claps);
+        assert_eq!(claps, vec![inner.join("vendor").join("plugin.clap")]);
+
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn stops_at_directory_symlink_loops() {
+        let root = temp_dir("symlink-loop");
+        let vendor = root.join("vendor");
+        std::fs::create_dir_all(&vendor).unwrap();
+        create_bundle(&vendor.join("plugin.clap"));
+        std::os::unix::fs::symlink(&root, vendor.join("loop")).unwrap();
+
+        let mut visited_dirs = HashMap::new();
+        let mut found_claps = HashSet::new();
+        let mut claps = Vec::new();
+        ClapScanner::find_claps(&root, 64, &mut visited_dirs, &mut found_claps, &mut claps);
+        assert_eq!(claps, vec![vendor.join("plugin.clap")]);
+
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn lists_bundles_reached_through_two_paths_once() {
+        let root = temp_dir("symlink-dedupe");
+        let vendor = root.join("vendor");
+        std::fs::create_dir_all(&vendor).unwrap();
+        create_bundle(&vendor.join("plugin.clap"));
+        std::os::unix::fs::symlink(vendor.join("plugin.clap"), root.join("alias.clap")).unwrap();
+
+        let mut visited_dirs = HashMap::new();
+        let mut found_claps = HashSet::new();
+        let mut claps = Vec::new();
+        ClapScanner::find_claps(&root, 1, &mut visited_dirs, &mut found_claps, &mut claps);
+        assert_eq!(claps, vec![root.join("alias.clap")]);
+
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    fn lookup(name: &str) -> Option<String> {
+        (name == "LOCALAPPDATA").then(|| "C:\\Users\\me\\AppData\\Local".to_string())
+    }
+
+    #[test]
+    fn keeps_the_last_lines() {
+        assert_eq!(ClapScanner::tail_lines(b"a\nb\nc\n", 2), "b\nc");
+        assert_eq!(ClapScanner::tail_lines(b"a\r\nb", 5), "a\nb");
+        assert_eq!(ClapScanner::tail_lines(b"", 3), "");
+        assert_eq!(ClapScanner::tail_lines(b"a\nb", 0), "");
+    }
+
+    #[test]
+    fn expands_percent_vars() {
+        assert_eq!(
+            ClapScanner::expand_percent_vars("%LOCALAPPDATA%\\Programs\\Common\\CLAP", lookup),
+            "C:\\Users\\me\\AppData\\Local\\Programs\\Common\\CLAP"
+        );
+    }
+
+    #[test]
+    fn keeps_unknown_and_unterminated_percent_vars() {
+        assert_eq!(
+            ClapScanner::expand_percent_vars("%MISSING%\\CLAP", lookup),
+            "%MISSING%\\CLAP"
+        );
+        assert_eq!(
+            ClapScanner::expand_percent_vars("C:\\100%\\CLAP", lookup),
+            "C:\\100%\\CLAP"
+        );
+        assert_eq!(ClapScanner::expand_percent_vars("a%%b", lookup), "a%%b");
     }
 }
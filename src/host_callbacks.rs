@@ -0,0 +1,140 @@
+use std::{
+    cell::Cell,
+    sync::{
+        Mutex,
+        atomic::{AtomicBool, Ordering},
+    },
+    thread::{self, ThreadId},
+    time::Instant,
+};
+
+use crate::Watchdog;
+
+thread_local! {
+    // Set on the thread that runs the plugin's audio processor
+    static IS_AUDIO_THREAD: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Mark the current thread as the audio thread (for clap.thread-check).
+pub(crate) fn enter_audio_thread() {
+    IS_AUDIO_THREAD.with(|is_audio_thread| is_audio_thread.set(true));
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum CallbackThread {
+    Main,
+    Audio,
+    Other,
+}
+
+/// A host callback invoked on a thread the CLAP specification forbids.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct InfoThreadViolation {
+    callback: String,
+    thread: CallbackThread,
+    phase: String,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct InfoHostRequest {
+    /// The host function, e.g. `request_restart` or `clap.latency/changed`
+    callback: String,
+    /// Milliseconds since the plugin started being queried
+    time_ms: f64,
+    thread: CallbackThread,
+    phase: String,
+}
+
+/// Keeps track of the host callbacks a plugin instance invokes, when and on which thread.
+#[derive(Debug)]
+pub struct HostCallbacks {
+    watchdog: Watchdog,
+    main_thread: ThreadId,
+    started_at: Instant,
+    requests: Mutex<Vec<InfoHostRequest>>,
+    violations: Mutex<Vec<InfoThreadViolation>>,
+    callback_requested: AtomicBool,
+}
+
+impl HostCallbacks {
+    /// The current thread is considered the main thread.
+    pub fn new(watchdog: Watchdog) -> Self {
+        Self {
+            watchdog,
+            main_thread: thread::current().id(),
+            started_at: Instant::now(),
+            requests: Mutex::new(Vec::new()),
+            violations: Mutex::new(Vec::new()),
+            callback_requested: AtomicBool::new(false),
+        }
+    }
+
+    pub fn current_thread(&self) -> CallbackThread {
+        if thread::current().id() == self.main_thread {
+            CallbackThread::Main
+        } else if IS_AUDIO_THREAD.with(Cell::get) {
+            CallbackThread::Audio
+        } else {
+            CallbackThread::Other
+        }
+    }
+
+    /// Record a call to a `[thread-safe]` callback, returning the thread it was invoked on.
+    pub fn record(&self, callback: &str) -> CallbackThread {
+        let thread = self.current_thread();
+        self.requests.lock().unwrap().push(InfoHostRequest {
+            callback: callback.to_string(),
+            time_ms: self.started_at.elapsed().as_secs_f64() * 1000.0,
+            thread,
+            phase: self.watchdog.phase().to_string(),
+        });
+        thread
+    }
+
+    /// Record a call to a `[main-thread]` callback, and a violation when called from
+    /// another thread.
+    pub fn record_main_thread(&self, callback: &str) {
+        let thread = self.record(callback);
+        if thread != CallbackThread::Main {
+            self.violation(callback, thread);
+        }
+    }
+
+    /// Record a call to a `[!audio-thread]` callback, and a violation when called from
+    /// the audio thread.
+    pub fn record_not_audio_thread(&self, callback: &str) {
+        let thread = self.record(callback);
+        if thread == CallbackThread::Audio {
+            self.violation(callback, thread);
+        }
+    }
+
+    pub fn request_callback(&self) {
+        self.record("request_callback");
+        self.callback_requested.store(true, Ordering::SeqCst);
+    }
+
+    /// Whether `on_main_thread` must be called, clearing the request.
+    pub fn take_callback_request(&self) -> bool {
+        self.callback_requested.swap(false, Ordering::SeqCst)
+    }
+
+    pub fn requests(&self) -> Vec<InfoHostRequest> {
+        self.requests.lock().unwrap().clone()
+    }
+
+    pub fn violations(&self) -> Vec<InfoThreadViolation> {
+        self.violations.lock().unwrap().clone()
+    }
+
+    fn violation(&self, callback: &str, thread: CallbackThread) {
+        self.violations.lock().unwrap().push(InfoThreadViolation {
+            callback: callback.to_string(),
+            thread,
+            phase: self.watchdog.phase().to_string(),
+        });
+    }
+}
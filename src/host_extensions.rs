@@ -0,0 +1,135 @@
+use std::ffi::CStr;
+
+use clack_extensions::audio_ports::{HostAudioPorts, HostAudioPortsImpl, RescanType};
+use clack_extensions::latency::{HostLatency, HostLatencyImpl};
+use clack_extensions::log::{HostLog, HostLogImpl, LogSeverity};
+use clack_extensions::params::{
+    HostParams, HostParamsImplMainThread, HostParamsImplShared, ParamClearFlags, ParamRescanFlags,
+};
+use clack_extensions::state::{HostState, HostStateImpl};
+use clack_extensions::thread_check::{HostThreadCheck, HostThreadCheckImpl};
+use clack_host::{extensions::HostExtensions, utils::ClapId};
+
+use crate::{CallbackThread, ClapInfoHost, ClapInfoMainThreadHandler, ClapInfoSharedHandler};
+
+pub(crate) const HOST_EXTENSIONS: [&str; 6] = [
+    "clap.log",
+    "clap.thread-check",
+    "clap.params",
+    "clap.latency",
+    "clap.audio-ports",
+    "clap.state",
+];
+
+/// The host extensions the plugin asked for with `get_extension`.
+#[derive(Clone, Default, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct InfoHostExtensions {
+    requested: Vec<String>,
+    /// The requested extensions the host doesn't provide
+    unsupported: Vec<String>,
+}
+
+impl InfoHostExtensions {
+    pub fn from_requests(requested: Vec<String>) -> Self {
+        let unsupported = requested
+            .iter()
+            .filter(|id| !HOST_EXTENSIONS.contains(&id.as_str()))
+            .cloned()
+            .collect();
+        Self {
+            requested,
+            unsupported,
+        }
+    }
+}
+
+impl ClapInfoHost {
+    // Called each time the plugin asks for a host extension
+    pub(crate) fn declare_host_extensions(
+        builder: &mut HostExtensions<Self>,
+        shared: &ClapInfoSharedHandler,
+    ) {
+        shared.record_extension_request(builder.requested());
+        builder
+            .register::<HostLog>()
+            .register::<HostThreadCheck>()
+            .register::<HostParams>()
+            .register::<HostLatency>()
+            .register::<HostAudioPorts>()
+            .register::<HostState>();
+    }
+}
+
+impl ClapInfoSharedHandler {
+    fn record_extension_request(&self, id: &CStr) {
+        let id = id.to_string_lossy().to_string();
+        let mut requested = self.requested_extensions.lock().unwrap();
+        if !requested.contains(&id) {
+            requested.push(id);
+        }
+    }
+}
+
+impl HostLogImpl for ClapInfoSharedHandler {
+    fn log(&self, severity: LogSeverity, message: &str) {
+        self.log.log(severity, message);
+    }
+}
+
+impl HostThreadCheckImpl for ClapInfoSharedHandler {
+    fn is_main_thread(&self) -> bool {
+        self.callbacks.current_thread() == CallbackThread::Main
+    }
+
+    fn is_audio_thread(&self) -> bool {
+        self.callbacks.current_thread() == CallbackThread::Audio
+    }
+}
+
+impl HostParamsImplShared for ClapInfoSharedHandler {
+    fn request_flush(&self) {
+        self.callbacks
+            .record_not_audio_thread("clap.params/request_flush");
+    }
+}
+
+impl HostParamsImplMainThread for ClapInfoMainThreadHandler<'_> {
+    fn rescan(&mut self, _flags: ParamRescanFlags) {
+        self.sh.callbacks.record_main_thread("clap.params/rescan");
+    }
+
+    fn clear(&mut self, _param_id: ClapId, _flags: ParamClearFlags) {
+        self.sh.callbacks.record_main_thread("clap.params/clear");
+    }
+}
+
+impl HostLatencyImpl for ClapInfoMainThreadHandler<'_> {
+    fn changed(&mut self) {
+        self.sh.callbacks.record_main_thread("clap.latency/changed");
+    }
+}
+
+impl HostAudioPortsImpl for ClapInfoMainThreadHandler<'_> {
+    // Nothing is cached on the host side, so any rescan is fine
+    fn is_rescan_flag_supported(&self, _flag: RescanType) -> bool {
+        self.sh
+            .callbacks
+            .record_main_thread("clap.audio-ports/is_rescan_flag_supported");
+        true
+    }
+
+    fn rescan(&mut self, _flag: RescanType) {
+        self.sh
+            .callbacks
+            .record_main_thread("clap.audio-ports/rescan");
+    }
+}
+
+impl HostStateImpl for ClapInfoMainThreadHandler<'_> {
+    fn mark_dirty(&mut self) {
+        self.sh
+            .callbacks
+            .record_main_thread("clap.state/mark_dirty");
+    }
+}
@@ -1,11 +1,17 @@
 use std::{collections::HashMap, ffi::CStr, path::PathBuf};
 
+use clack_extensions::preset_discovery::PresetDiscoveryFactory;
 use clack_host::{
     bundle::PluginBundle,
     factory::{PluginDescriptor, PluginFactory},
 };
 
-#[derive(serde::Serialize)]
+use crate::{
+    ClapInfoHostError, InfoActivationMatrix, InfoHostExtensions, InfoHostRequest, InfoLifecycle,
+    InfoLifecycleStress, InfoLogMessage, InfoPresetDiscovery, InfoThreadViolation,
+};
+
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
 #[serde(rename_all = "kebab-case")]
 pub struct InfoBundle {
     clap_version: String,
@@ -13,12 +19,22 @@ pub struct InfoBundle {
     #[serde(skip_serializing_if = "Option::is_none")]
     bundle_file: Option<String>,
     plugins: Vec<InfoPlugin>,
+    #[serde(default)]
+    factories: Vec<InfoFactory>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    preset_discovery: Option<InfoPresetDiscovery>,
 }
 
 impl InfoBundle {
-    pub fn new(path: String, bundle: &PluginBundle, bundle_file: Option<PathBuf>) -> Self {
-        let factory = bundle.get_factory::<PluginFactory<'_>>().unwrap();
-        Self {
+    pub fn new(
+        path: String,
+        bundle: &PluginBundle,
+        bundle_file: Option<PathBuf>,
+    ) -> Result<Self, ClapInfoHostError> {
+        let factory = bundle
+            .get_factory::<PluginFactory<'_>>()
+            .ok_or(ClapInfoHostError::MissingPluginFactory)?;
+        Ok(Self {
             clap_version: format!("{}", bundle.version()),
             path,
             bundle_file: bundle_file.map(|path| path.display().to_string()),
@@ -26,17 +42,135 @@ impl InfoBundle {
                 .plugin_descriptors()
                 .map(|descriptor| InfoPlugin::from_descriptor(&descriptor))
                 .collect(),
-        }
+            factories: InfoFactory::from_bundle(bundle),
+            preset_discovery: None,
+        })
+    }
+
+    pub fn get_plugin_mut(&mut self, index: usize) -> Result<&mut InfoPlugin, ClapInfoHostError> {
+        self.plugins
+            .get_mut(index)
+            .ok_or(ClapInfoHostError::InvalidPluginIndex(index))
+    }
+
+    pub fn plugin_count(&self) -> usize {
+        self.plugins.len()
+    }
+
+    pub fn set_preset_discovery(&mut self, preset_discovery: InfoPresetDiscovery) {
+        self.preset_discovery = Some(preset_discovery);
+    }
+}
+
+// How to summarize a factory, for the ids we have a typed wrapper for
+type SummarizeFactory = fn(&mut InfoFactory, &PluginBundle);
+
+// The factory ids a bundle may export, along with a short name for the output and
+// how to summarize them. Draft ids have no typed wrapper: they're only listed.
+const KNOWN_FACTORIES: [(&CStr, &str, Option<SummarizeFactory>); 6] = [
+    (
+        c"clap.plugin-factory",
+        "plugin",
+        Some(InfoFactory::summarize_plugin_factory),
+    ),
+    (
+        c"clap.preset-discovery-factory/2",
+        "preset-discovery",
+        Some(InfoFactory::summarize_preset_discovery_factory),
+    ),
+    (
+        c"clap.preset-discovery-factory/draft-2",
+        "preset-discovery (draft)",
+        None,
+    ),
+    (
+        c"clap.plugin-invalidation-factory/1",
+        "plugin-invalidation",
+        None,
+    ),
+    (
+        c"clap.plugin-state-converter-factory/1",
+        "state-converter",
+        None,
+    ),
+    (
+        c"clap.plugin-state-converter-factory/draft-1",
+        "state-converter (draft)",
+        None,
+    ),
+];
+
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct InfoFactory {
+    id: String,
+    name: String,
+    /// Number of plugins, for the plugin factory
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    plugin_count: Option<u32>,
+    /// Ids of the preset providers, for the preset-discovery factory
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    preset_providers: Option<Vec<String>>,
+}
+
+impl InfoFactory {
+    /// The known factories for which the bundle's `get_factory` returns a non-null pointer.
+    pub fn from_bundle(bundle: &PluginBundle) -> Vec<Self> {
+        let Some(get_factory) = bundle.raw_entry().get_factory else {
+            return Vec::new();
+        };
+
+        KNOWN_FACTORIES
+            .into_iter()
+            // SAFETY: the bundle entry stays initialized while the bundle is loaded,
+            // and the id is a valid null-terminated string.
+            .filter(|(id, _, _)| !unsafe { get_factory(id.as_ptr()) }.is_null())
+            .map(|(id, name, summarize)| {
+                let mut factory = Self {
+                    id: id.to_string_lossy().to_string(),
+                    name: name.to_string(),
+                    plugin_count: None,
+                    preset_providers: None,
+                };
+                if let Some(summarize) = summarize {
+                    summarize(&mut factory, bundle);
+                }
+                factory
+            })
+            .collect()
+    }
+
+    fn summarize_plugin_factory(&mut self, bundle: &PluginBundle) {
+        self.plugin_count = bundle
+            .get_factory::<PluginFactory<'_>>()
+            .map(|factory| factory.plugin_count());
     }
 
-    pub fn get_plugin_mut(&mut self, index: usize) -> &mut InfoPlugin {
-        &mut self.plugins[index]
+    fn summarize_preset_discovery_factory(&mut self, bundle: &PluginBundle) {
+        self.preset_providers = bundle
+            .get_factory::<PresetDiscoveryFactory>()
+            .map(|factory| {
+                (0..factory.provider_count())
+                    .filter_map(|index| factory.provider_descriptor(index))
+                    .filter_map(|descriptor| descriptor.id())
+                    .map(|id| id.to_string_lossy().to_string())
+                    .collect()
+            });
     }
 }
 
+#[derive(Clone)]
 pub struct InfoPlugin {
     descriptor: InfoPluginDescriptor,
     extensions: Option<HashMap<String, serde_json::Value>>,
+    host_extensions: Option<InfoHostExtensions>,
+    lifecycle: InfoLifecycle,
+    lifecycle_stress: Option<InfoLifecycleStress>,
+    log: Vec<InfoLogMessage>,
+    host_requests: Vec<InfoHostRequest>,
+    thread_violations: Vec<InfoThreadViolation>,
+    activation_matrix: Option<InfoActivationMatrix>,
+    error: Option<serde_json::Value>,
 }
 
 impl InfoPlugin {
@@ -44,13 +178,63 @@ impl InfoPlugin {
         Self {
             descriptor: InfoPluginDescriptor::from_descriptor(descriptor),
             extensions: None,
+            host_extensions: None,
+            lifecycle: InfoLifecycle::default(),
+            lifecycle_stress: None,
+            log: Vec::new(),
+            host_requests: Vec::new(),
+            thread_violations: Vec::new(),
+            activation_matrix: None,
+            error: None,
         }
     }
 
-    pub fn add_extension<T: serde::Serialize>(&mut self, key: &str, value: T) {
+    pub fn extensions(&self) -> Option<&HashMap<String, serde_json::Value>> {
+        self.extensions.as_ref()
+    }
+
+    pub fn set_host_extensions(&mut self, host_extensions: InfoHostExtensions) {
+        self.host_extensions = Some(host_extensions);
+    }
+
+    pub fn set_lifecycle(&mut self, lifecycle: InfoLifecycle) {
+        self.lifecycle = lifecycle;
+    }
+
+    pub fn set_lifecycle_stress(&mut self, lifecycle_stress: InfoLifecycleStress) {
+        self.lifecycle_stress = Some(lifecycle_stress);
+    }
+
+    pub fn set_log(&mut self, log: Vec<InfoLogMessage>) {
+        self.log = log;
+    }
+
+    pub fn set_host_requests(&mut self, host_requests: Vec<InfoHostRequest>) {
+        self.host_requests = host_requests;
+    }
+
+    pub fn set_thread_violations(&mut self, thread_violations: Vec<InfoThreadViolation>) {
+        self.thread_violations = thread_violations;
+    }
+
+    pub fn set_activation_matrix(&mut self, activation_matrix: InfoActivationMatrix) {
+        self.activation_matrix = Some(activation_matrix);
+    }
+
+    /// Record why querying this plugin failed, as a `{ "kind": ..., "message": ... }` object.
+    pub fn set_error(&mut self, error: &ClapInfoHostError) {
+        self.error = serde_json::to_value(error).ok();
+    }
+
+    pub fn add_extension<T: serde::Serialize>(
+        &mut self,
+        key: &str,
+        value: T,
+    ) -> Result<(), ClapInfoHostError> {
         self.extensions
             .get_or_insert(HashMap::new())
-            .insert(key.to_string(), serde_json::to_value(value).unwrap());
+            .insert(key.to_string(), serde_json::to_value(value)?);
+        Ok(())
     }
 }
 
@@ -62,21 +246,118 @@ impl serde::Serialize for InfoPlugin {
     where
         S: serde::Serializer,
     {
-        if self.extensions.is_none() || self.extensions.as_ref().unwrap().is_empty() {
+        let has_extensions = self.extensions.as_ref().is_some_and(|e| !e.is_empty());
+        let has_reports = !self.lifecycle.is_empty()
+            || !self.log.is_empty()
+            || !self.host_requests.is_empty()
+            || !self.thread_violations.is_empty();
+        if !has_extensions && !has_reports && self.error.is_none() {
             // If no extensions, just serialize the descriptor
             self.descriptor.serialize(serializer)
         } else {
-            // Otherwise, serialize both descriptor and extensions
+            // Otherwise, serialize both descriptor and extensions (and the log and error, if any)
             use serde::ser::SerializeStruct;
-            let mut state = serializer.serialize_struct("InfoPlugin", 2)?;
+            let mut state = serializer.serialize_struct("InfoPlugin", 10)?;
             state.serialize_field("descriptor", &self.descriptor)?;
-            state.serialize_field("extensions", &self.extensions)?;
+            if has_extensions {
+                state.serialize_field("extensions", &self.extensions)?;
+            }
+            if let Some(host_extensions) = &self.host_extensions {
+                state.serialize_field("host-extensions", host_extensions)?;
+            }
+            if !self.lifecycle.is_empty() {
+                state.serialize_field("lifecycle", &self.lifecycle)?;
+            }
+            if let Some(lifecycle_stress) = &self.lifecycle_stress {
+                state.serialize_field("lifecycle-stress", lifecycle_stress)?;
+            }
+            if !self.log.is_empty() {
+                state.serialize_field("log", &self.log)?;
+            }
+            if !self.host_requests.is_empty() {
+                state.serialize_field("host-requests", &self.host_requests)?;
+            }
+            if !self.thread_violations.is_empty() {
+                state.serialize_field("thread-violations", &self.thread_violations)?;
+            }
+            if let Some(activation_matrix) = &self.activation_matrix {
+                state.serialize_field("activation-matrix", activation_matrix)?;
+            }
+            if let Some(error) = &self.error {
+                state.serialize_field("error", error)?;
+            }
             state.end()
         }
     }
 }
 
-#[derive(Debug, serde::Serialize)]
+// Counterpart of the custom serialization: accept both the descriptor-only
+// and the descriptor + extensions representations.
+impl<'de> serde::Deserialize<'de> for InfoPlugin {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        #[derive(serde::Deserialize)]
+        struct WithExtensions {
+            descriptor: InfoPluginDescriptor,
+            #[serde(default)]
+            extensions: Option<HashMap<String, serde_json::Value>>,
+            #[serde(default, rename = "host-extensions")]
+            host_extensions: Option<InfoHostExtensions>,
+            #[serde(default)]
+            lifecycle: InfoLifecycle,
+            #[serde(default, rename = "lifecycle-stress")]
+            lifecycle_stress: Option<InfoLifecycleStress>,
+            #[serde(default)]
+            log: Vec<InfoLogMessage>,
+            #[serde(default, rename = "host-requests")]
+            host_requests: Vec<InfoHostRequest>,
+            #[serde(default, rename = "thread-violations")]
+            thread_violations: Vec<InfoThreadViolation>,
+            #[serde(default, rename = "activation-matrix")]
+            activation_matrix: Option<InfoActivationMatrix>,
+            #[serde(default)]
+            error: Option<serde_json::Value>,
+        }
+
+        #[derive(serde::Deserialize)]
+        #[serde(untagged)]
+        enum Repr {
+            WithExtensions(Box<WithExtensions>),
+            DescriptorOnly(InfoPluginDescriptor),
+        }
+
+        Ok(match Repr::deserialize(deserializer)? {
+            Repr::WithExtensions(plugin) => Self {
+                descriptor: plugin.descriptor,
+                extensions: plugin.extensions,
+                host_extensions: plugin.host_extensions,
+                lifecycle: plugin.lifecycle,
+                lifecycle_stress: plugin.lifecycle_stress,
+                log: plugin.log,
+                host_requests: plugin.host_requests,
+                thread_violations: plugin.thread_violations,
+                activation_matrix: plugin.activation_matrix,
+                error: plugin.error,
+            },
+            Repr::DescriptorOnly(descriptor) => Self {
+                descriptor,
+                extensions: None,
+                host_extensions: None,
+                lifecycle: InfoLifecycle::default(),
+                lifecycle_stress: None,
+                log: Vec::new(),
+                host_requests: Vec::new(),
+                thread_violations: Vec::new(),
+                activation_matrix: None,
+                error: None,
+            },
+        })
+    }
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 #[serde(rename_all = "kebab-case")]
 pub struct InfoPluginDescriptor {
     id: String,
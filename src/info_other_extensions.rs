@@ -1,10 +1,14 @@
 use clack_extensions::audio_ports::AudioPortType;
-use clack_extensions::gui::{GuiApiType, GuiConfiguration, PluginGui};
+use clack_extensions::gui::{
+    AspectRatioStrategy, GuiApiType, GuiConfiguration, GuiResizeHints, GuiSize, PluginGui,
+};
 use clack_extensions::latency::PluginLatency;
 use clack_extensions::note_name::{NoteName, NoteNameBuffer, PluginNoteName};
+use clack_extensions::render::{PluginRender, RenderMode};
 use clack_extensions::state::PluginState;
 use clack_extensions::tail::{PluginTail, TailLength};
-use clack_host::plugin::PluginMainThreadHandle;
+use clack_extensions::voice_info::{PluginVoiceInfo, VoiceInfoFlags};
+use clack_host::plugin::{PluginAudioProcessorHandle, PluginMainThreadHandle};
 use std::collections::HashMap;
 use std::io::Write;
 
@@ -39,18 +43,46 @@ impl InfoLatencyExtension {
 #[serde(rename_all = "kebab-case")]
 pub struct InfoTailExtension {
     implemented: bool,
-    tail: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tail: Option<InfoTailLength>,
+}
+
+/// The tail length, serialized as a number of samples or as `"infinite"`.
+pub enum InfoTailLength {
+    Finite(u32),
+    Infinite,
+}
+
+impl serde::Serialize for InfoTailLength {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        match self {
+            InfoTailLength::Finite(samples) => serializer.serialize_u32(*samples),
+            InfoTailLength::Infinite => serializer.serialize_str("infinite"),
+        }
+    }
 }
 
 impl InfoTailExtension {
-    pub fn from_plugin(plugin: &mut PluginMainThreadHandle) -> Self {
-        let extension = plugin.get_extension::<PluginTail>();
+    // clap.tail can only be queried from the audio thread, so it needs a started
+    // audio processor. Without one, only the extension presence is reported.
+    pub fn from_processor(
+        extension: Option<PluginTail>,
+        processor: Option<&mut PluginAudioProcessorHandle>,
+    ) -> Self {
         let mut implemented = false;
-        let tail = 0;
+        let mut tail = None;
 
-        if let Some(_extension) = extension {
+        if let Some(extension) = extension {
             implemented = true;
-            // tail = extension.get(plugin);  // This line seems to be missing
+            if let Some(processor) = processor {
+                tail = Some(match extension.get(processor) {
+                    TailLength::Finite(samples) => InfoTailLength::Finite(samples),
+                    TailLength::Infinite => InfoTailLength::Infinite,
+                });
+            }
         }
 
         Self { implemented, tail }
@@ -79,6 +111,145 @@ pub struct InfoGuiExtension {
     api_supported: Option<Vec<String>>,
     #[serde(skip_serializing_if = "Option::is_none")]
     preferred_api: Option<PreferredApi>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    window: Option<InfoGuiWindow>,
+}
+
+// Sizes given to adjust_size, to see whether the plugin clamps them
+const GUI_TEST_SIZES: [(u32, u32); 4] = [(1, 1), (640, 480), (1234, 567), (16384, 16384)];
+
+/// What the plugin reports once its GUI is created (but never shown).
+#[derive(serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct InfoGuiWindow {
+    api: String,
+    floating: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    create_error: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    size: Option<InfoGuiSize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    can_resize: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    resize_hints: Option<InfoGuiResizeHints>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    adjust_size: Vec<InfoGuiAdjustSize>,
+}
+
+#[derive(Clone, Copy, PartialEq, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct InfoGuiSize {
+    width: u32,
+    height: u32,
+}
+
+impl From<GuiSize> for InfoGuiSize {
+    fn from(size: GuiSize) -> Self {
+        Self {
+            width: size.width,
+            height: size.height,
+        }
+    }
+}
+
+#[derive(serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct InfoGuiResizeHints {
+    can_resize_horizontally: bool,
+    can_resize_vertically: bool,
+    preserve_aspect_ratio: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    aspect_ratio: Option<InfoGuiSize>,
+}
+
+impl From<GuiResizeHints> for InfoGuiResizeHints {
+    fn from(hints: GuiResizeHints) -> Self {
+        let aspect_ratio = match hints.strategy {
+            AspectRatioStrategy::Preserve { width, height } => Some(InfoGuiSize { width, height }),
+            AspectRatioStrategy::Disregard => None,
+        };
+        Self {
+            can_resize_horizontally: hints.can_resize_horizontally,
+            can_resize_vertically: hints.can_resize_vertically,
+            preserve_aspect_ratio: aspect_ratio.is_some(),
+            aspect_ratio,
+        }
+    }
+}
+
+#[derive(serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct InfoGuiAdjustSize {
+    requested: InfoGuiSize,
+    /// None when the plugin refuses the size
+    adjusted: Option<InfoGuiSize>,
+    clamped: bool,
+}
+
+impl InfoGuiWindow {
+    // Create the GUI without a parent window and without showing it, query its
+    // size and resize behaviour, then destroy it.
+    fn from_plugin(
+        plugin: &mut PluginMainThreadHandle,
+        extension: PluginGui,
+        config: GuiConfiguration,
+    ) -> Self {
+        let mut window = Self {
+            api: config.api_type.0.to_string_lossy().to_string(),
+            floating: config.is_floating,
+            create_error: None,
+            size: None,
+            can_resize: None,
+            resize_hints: None,
+            adjust_size: Vec::new(),
+        };
+
+        // Plugins tend to crash rather than fail when there's no display to connect to
+        if let Some(variable) = Self::missing_display(config.api_type) {
+            window.create_error = Some(format!("No display server (${} is not set)", variable));
+            return window;
+        }
+
+        if let Err(err) = extension.create(plugin, config) {
+            window.create_error = Some(err.to_string());
+            return window;
+        }
+
+        window.size = extension.get_size(plugin).map(InfoGuiSize::from);
+        let can_resize = extension.can_resize(plugin);
+        window.can_resize = Some(can_resize);
+        window.resize_hints = extension
+            .get_resize_hints(plugin)
+            .map(InfoGuiResizeHints::from);
+        if can_resize {
+            for (width, height) in GUI_TEST_SIZES {
+                let requested = InfoGuiSize { width, height };
+                let adjusted = extension
+                    .adjust_size(plugin, GuiSize { width, height })
+                    .map(InfoGuiSize::from);
+                window.adjust_size.push(InfoGuiAdjustSize {
+                    requested,
+                    adjusted,
+                    clamped: adjusted.is_some_and(|adjusted| adjusted != requested),
+                });
+            }
+        }
+
+        extension.destroy(plugin);
+        window
+    }
+
+    // The environment variable that must be set to create a window with the given API
+    fn missing_display(api_type: GuiApiType) -> Option<&'static str> {
+        let variable = if api_type == GuiApiType::X11 {
+            "DISPLAY"
+        } else if api_type == GuiApiType::WAYLAND {
+            "WAYLAND_DISPLAY"
+        } else {
+            return None;
+        };
+        std::env::var_os(variable).is_none().then_some(variable)
+    }
 }
 
 impl InfoGuiExtension {
@@ -87,11 +258,13 @@ impl InfoGuiExtension {
         let mut implemented = false;
         let mut api_supported = None;
         let mut preferred_api = None;
+        let mut window = None;
 
         if let Some(extension) = extension {
             implemented = true;
 
             let mut supported_apis = Vec::new();
+            let mut supported_configs = Vec::new();
 
             // Check support for different window APIs
             let apis = [
@@ -109,6 +282,7 @@ impl InfoGuiExtension {
                 };
                 if extension.is_api_supported(plugin, config) {
                     supported_apis.push(api.1.to_string());
+                    supported_configs.push(config);
                 }
 
                 let config_floating = GuiConfiguration {
@@ -117,6 +291,7 @@ impl InfoGuiExtension {
                 };
                 if extension.is_api_supported(plugin, config_floating) {
                     supported_apis.push(format!("{}.floating", api.1));
+                    supported_configs.push(config_floating);
                 }
             }
 
@@ -125,18 +300,28 @@ impl InfoGuiExtension {
             }
 
             // Get preferred API
-            if let Some(config) = extension.get_preferred_api(plugin) {
+            let preferred_config = extension.get_preferred_api(plugin);
+            if let Some(config) = &preferred_config {
                 preferred_api = Some(PreferredApi {
                     api: config.api_type.0.to_string_lossy().to_string(),
                     floating: config.is_floating,
                 });
             }
+
+            // Create the GUI with the preferred API, or else the first supported one
+            let config = preferred_config
+                .filter(|config| extension.is_api_supported(plugin, *config))
+                .or_else(|| supported_configs.first().copied());
+            if let Some(config) = config {
+                window = Some(InfoGuiWindow::from_plugin(plugin, extension, config));
+            }
         }
 
         Self {
             implemented,
             api_supported,
             preferred_api,
+            window,
         }
     }
 }
@@ -201,7 +386,7 @@ pub struct InfoNoteName {
 #[serde(rename_all = "kebab-case")]
 pub struct InfoNoteNameExtension {
     implemented: bool,
-    count: u32,
+    count: usize,
     #[serde(skip_serializing_if = "Option::is_none")]
     note_names: Option<Vec<InfoNoteName>>,
 }
@@ -258,8 +443,89 @@ impl InfoNoteNameExtension {
 
         Self {
             implemented,
-            count: count.try_into().unwrap(),
+            count,
             note_names,
         }
     }
 }
+
+#[derive(serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct InfoVoiceInfoExtension {
+    implemented: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    voice_count: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    voice_capacity: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    supports_overlapping_notes: Option<bool>,
+}
+
+impl InfoVoiceInfoExtension {
+    // The voice info is only meaningful once the plugin is activated
+    pub fn from_plugin(plugin: &mut PluginMainThreadHandle) -> Self {
+        let extension = plugin.get_extension::<PluginVoiceInfo>();
+        let mut implemented = false;
+        let mut voice_count = None;
+        let mut voice_capacity = None;
+        let mut supports_overlapping_notes = None;
+
+        if let Some(extension) = extension {
+            implemented = true;
+
+            if let Some(voice_info) = extension.get(plugin) {
+                voice_count = Some(voice_info.voice_count);
+                voice_capacity = Some(voice_info.voice_capacity);
+                supports_overlapping_notes = Some(
+                    voice_info
+                        .flags
+                        .contains(VoiceInfoFlags::SUPPORTS_OVERLAPPING_NOTES),
+                );
+            }
+        }
+
+        Self {
+            implemented,
+            voice_count,
+            voice_capacity,
+            supports_overlapping_notes,
+        }
+    }
+}
+
+#[derive(serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct InfoRenderExtension {
+    implemented: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    has_hard_realtime_requirement: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    accepts_offline: Option<bool>,
+}
+
+impl InfoRenderExtension {
+    pub fn from_plugin(plugin: &mut PluginMainThreadHandle) -> Self {
+        let extension = plugin.get_extension::<PluginRender>();
+        let mut implemented = false;
+        let mut has_hard_realtime_requirement = None;
+        let mut accepts_offline = None;
+
+        if let Some(extension) = extension {
+            implemented = true;
+            has_hard_realtime_requirement = Some(extension.has_hard_realtime_requirement(plugin));
+
+            // Try switching to offline rendering, then go back to the default mode
+            let offline = extension.set(plugin, RenderMode::Offline);
+            if offline {
+                extension.set(plugin, RenderMode::Realtime);
+            }
+            accepts_offline = Some(offline);
+        }
+
+        Self {
+            implemented,
+            has_hard_realtime_requirement,
+            accepts_offline,
+        }
+    }
+}
@@ -1,16 +1,26 @@
+use std::mem::MaybeUninit;
+
 use clack_extensions::params::{ParamInfo, ParamInfoBuffer, ParamInfoFlags, PluginParams};
-use clack_host::plugin::PluginMainThreadHandle;
+use clack_host::{plugin::PluginMainThreadHandle, utils::ClapId};
+
+use crate::{InfoParamRoundTrip, QueryOptions};
+
+// Size of the buffer given to value_to_text
+const VALUE_TEXT_SIZE: usize = 256;
 
 #[derive(serde::Serialize)]
 #[serde(rename_all = "kebab-case")]
 pub struct InfoParamsExtension {
     implemented: bool,
     param_count: u32,
-    params: Vec<InfoParam>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    params: Option<Vec<InfoParam>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    params_tree: Option<InfoParamModule>,
 }
 
 impl InfoParamsExtension {
-    pub fn from_plugin(plugin: &mut PluginMainThreadHandle) -> Self {
+    pub fn from_plugin(plugin: &mut PluginMainThreadHandle, options: &QueryOptions) -> Self {
         let mut param_list = Vec::new();
         let mut implemented = false;
         let mut param_count = 0;
@@ -23,14 +33,30 @@ impl InfoParamsExtension {
             for i in 0..param_count {
                 let param_info = params.get_info(plugin, i, &mut buffer);
                 if let Some(param_info) = param_info {
-                    param_list.push(InfoParam::from_param_info(&param_info));
+                    let mut param = InfoParam::from_param_info(&param_info, &params, plugin);
+                    if options.params_roundtrip {
+                        param.roundtrip = Some(InfoParamRoundTrip::from_param_info(
+                            &param_info,
+                            &params,
+                            plugin,
+                        ));
+                    }
+                    param_list.push(param);
                 }
             }
         }
+        // Either output the flat list or the module hierarchy
+        let (params, params_tree) = if options.params_tree {
+            (None, Some(InfoParamModule::from_params(param_list)))
+        } else {
+            (Some(param_list), None)
+        };
+
         return Self {
             implemented,
             param_count,
-            params: param_list,
+            params,
+            params_tree,
         };
     }
 }
@@ -40,25 +66,60 @@ impl InfoParamsExtension {
 pub struct InfoParam {
     id: String,
     name: String,
+    module: String,
+    has_cookie: bool,
     flags: Vec<&'static str>,
     values: InfoParamValue,
+    texts: InfoParamText,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    roundtrip: Option<InfoParamRoundTrip>,
 }
 
 impl InfoParam {
-    pub fn from_param_info(param_info: &ParamInfo) -> Self {
+    pub fn from_param_info(
+        param_info: &ParamInfo,
+        params: &PluginParams,
+        plugin: &mut PluginMainThreadHandle,
+    ) -> Self {
+        let current = params.get_value(plugin, param_info.id);
+        let mut value_to_text = |value: Option<f64>| {
+            value.and_then(|value| Self::value_to_text(params, plugin, param_info.id, value))
+        };
+
         Self {
             id: format!("0x{:08x}", u32::from(param_info.id)),
             name: String::from_utf8_lossy(param_info.name).to_string(),
+            module: String::from_utf8_lossy(param_info.module).to_string(),
+            has_cookie: !param_info.cookie.as_raw().is_null(),
             flags: Self::flags_to_vec(param_info.flags),
             values: InfoParamValue {
-                current: param_info.default_value,
+                current,
                 default: param_info.default_value,
                 min: param_info.min_value,
                 max: param_info.max_value,
             },
+            texts: InfoParamText {
+                current: value_to_text(current),
+                default: value_to_text(Some(param_info.default_value)),
+                min: value_to_text(Some(param_info.min_value)),
+                max: value_to_text(Some(param_info.max_value)),
+            },
+            roundtrip: None,
         }
     }
 
+    // Returns the text the plugin displays for the given value
+    pub(crate) fn value_to_text(
+        params: &PluginParams,
+        plugin: &mut PluginMainThreadHandle,
+        param_id: ClapId,
+        value: f64,
+    ) -> Option<String> {
+        let mut buffer = [MaybeUninit::uninit(); VALUE_TEXT_SIZE];
+        let text = params.value_to_text(plugin, param_id, value, &mut buffer)?;
+        Some(String::from_utf8_lossy(text).to_string())
+    }
+
     fn flags_to_vec(flags: ParamInfoFlags) -> Vec<&'static str> {
         let mut result = Vec::new();
 
@@ -120,8 +181,140 @@ impl InfoParam {
 #[derive(serde::Serialize)]
 #[serde(rename_all = "kebab-case")]
 pub struct InfoParamValue {
-    current: f64,
+    current: Option<f64>,
     default: f64,
     min: f64,
     max: f64,
 }
+
+// The values as rendered by the plugin's value_to_text
+#[derive(serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct InfoParamText {
+    current: Option<String>,
+    default: Option<String>,
+    min: Option<String>,
+    max: Option<String>,
+}
+
+/// Parameters grouped by their module path (e.g. `Oscillators/Osc 1`).
+#[derive(serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct InfoParamModule {
+    name: String,
+    path: String,
+    params: Vec<InfoParam>,
+    modules: Vec<InfoParamModule>,
+}
+
+impl InfoParamModule {
+    fn new(name: &str, path: String) -> Self {
+        Self {
+            name: name.to_string(),
+            path,
+            params: Vec::new(),
+            modules: Vec::new(),
+        }
+    }
+
+    /// Build the module hierarchy, keeping modules and parameters in plugin order.
+    pub fn from_params(params: Vec<InfoParam>) -> Self {
+        let mut root = Self::new("", String::new());
+
+        for param in params {
+            let mut module = &mut root;
+            for name in param.module.split('/').filter(|name| !name.is_empty()) {
+                let index = match module.modules.iter().position(|m| m.name == name) {
+                    Some(index) => index,
+                    None => {
+                        let path = if module.path.is_empty() {
+                            name.to_string()
+                        } else {
+                            format!("{}/{}", module.path, name)
+                        };
+                        module.modules.push(Self::new(name, path));
+                        module.modules.len() - 1
+                    }
+                };
+                module = &mut module.modules[index];
+            }
+            module.params.push(param);
+        }
+
+        root
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn param(name: &str, module: &str) -> InfoParam {
+        InfoParam {
+            id: String::new(),
+            name: name.to_string(),
+            module: module.to_string(),
+            has_cookie: false,
+            flags: Vec::new(),
+            values: InfoParamValue {
+                current: None,
+                default: 0.0,
+                min: 0.0,
+                max: 1.0,
+            },
+            texts: InfoParamText {
+                current: None,
+                default: None,
+                min: None,
+                max: None,
+            },
+            roundtrip: None,
+        }
+    }
+
+    fn names(params: &[InfoParam]) -> Vec<&str> {
+        params.iter().map(|param| param.name.as_str()).collect()
+    }
+
+    #[test]
+    fn groups_params_by_module_path() {
+        let root = InfoParamModule::from_params(vec![
+            param("gain", ""),
+            param("osc 1 pitch", "Oscillators/Osc 1"),
+            param("filter cutoff", "Filter"),
+            param("osc 2 pitch", "Oscillators/Osc 2"),
+            param("osc 1 shape", "Oscillators/Osc 1"),
+        ]);
+
+        assert_eq!(names(&root.params), ["gain"]);
+        let module_names = root
+            .modules
+            .iter()
+            .map(|m| m.name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(module_names, ["Oscillators", "Filter"]);
+
+        let oscillators = &root.modules[0];
+        assert!(oscillators.params.is_empty());
+        assert_eq!(oscillators.modules[0].path, "Oscillators/Osc 1");
+        assert_eq!(
+            names(&oscillators.modules[0].params),
+            ["osc 1 pitch", "osc 1 shape"]
+        );
+        assert_eq!(oscillators.modules[1].path, "Oscillators/Osc 2");
+        assert_eq!(names(&root.modules[1].params), ["filter cutoff"]);
+    }
+
+    #[test]
+    fn ignores_empty_path_segments() {
+        let root = InfoParamModule::from_params(vec![
+            param("cutoff", "/Filter/"),
+            param("resonance", "Filter//"),
+        ]);
+
+        assert!(root.params.is_empty());
+        assert_eq!(root.modules.len(), 1);
+        assert_eq!(root.modules[0].path, "Filter");
+        assert_eq!(names(&root.modules[0].params), ["cutoff", "resonance"]);
+    }
+}
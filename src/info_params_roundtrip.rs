@@ -0,0 +1,175 @@
+use std::ffi::CString;
+
+use clack_extensions::params::{ParamInfo, ParamInfoFlags, PluginParams};
+use clack_host::plugin::PluginMainThreadHandle;
+
+use crate::InfoParam;
+
+// How many values are sampled across the range of a continuous parameter
+const CONTINUOUS_SAMPLES: usize = 17;
+// Stepped parameters with more steps than this are sampled like continuous ones,
+// each sample being rounded to the nearest step
+const MAX_STEPS: f64 = 1024.0;
+
+/// The result of converting sampled values of a parameter to text and back.
+#[derive(Default, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct InfoParamRoundTrip {
+    samples: usize,
+    /// Values whose text, once parsed back and rendered again, gives a different text
+    mismatches: Vec<InfoRoundTripMismatch>,
+    unparsable: Vec<InfoRoundTripText>,
+    no_text: Vec<f64>,
+    /// Whether the numbers in the texts go up and down while the value increases
+    non_monotonic: bool,
+}
+
+#[derive(serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct InfoRoundTripMismatch {
+    value: f64,
+    text: String,
+    parsed: f64,
+    parsed_text: Option<String>,
+}
+
+#[derive(serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct InfoRoundTripText {
+    value: f64,
+    text: String,
+}
+
+impl InfoParamRoundTrip {
+    /// Sample the parameter across `[min, max]` (every step for stepped parameters, or
+    /// evenly spaced steps when there are too many of them)
+    /// and run value -> text -> value on each sample.
+    pub fn from_param_info(
+        param_info: &ParamInfo,
+        params: &PluginParams,
+        plugin: &mut PluginMainThreadHandle,
+    ) -> Self {
+        let values = Self::sample_values(param_info);
+        let mut result = Self {
+            samples: values.len(),
+            ..Self::default()
+        };
+        let mut numbers = Vec::new();
+
+        for value in values {
+            let Some(text) = InfoParam::value_to_text(params, plugin, param_info.id, value) else {
+                result.no_text.push(value);
+                continue;
+            };
+            numbers.push(Self::leading_number(&text));
+
+            let parsed = CString::new(text.clone())
+                .ok()
+                .and_then(|display| params.text_to_value(plugin, param_info.id, &display));
+            let Some(parsed) = parsed else {
+                result.unparsable.push(InfoRoundTripText { value, text });
+                continue;
+            };
+
+            // Display texts are usually rounded, so compare texts rather than values
+            let parsed_text = InfoParam::value_to_text(params, plugin, param_info.id, parsed);
+            if parsed_text.as_deref() != Some(text.as_str()) {
+                result.mismatches.push(InfoRoundTripMismatch {
+                    value,
+                    text,
+                    parsed,
+                    parsed_text,
+                });
+            }
+        }
+
+        // Only meaningful when every text starts with a number
+        if let Some(numbers) = numbers.into_iter().collect::<Option<Vec<_>>>() {
+            let increasing = numbers.windows(2).all(|pair| pair[0] <= pair[1]);
+            let decreasing = numbers.windows(2).all(|pair| pair[0] >= pair[1]);
+            result.non_monotonic = !increasing && !decreasing;
+        }
+
+        result
+    }
+
+    fn sample_values(param_info: &ParamInfo) -> Vec<f64> {
+        Self::sample_range(
+            param_info.min_value,
+            param_info.max_value,
+            param_info.flags.contains(ParamInfoFlags::IS_STEPPED),
+        )
+    }
+
+    fn sample_range(min: f64, max: f64, stepped: bool) -> Vec<f64> {
+        if !min.is_finite() || !max.is_finite() || min > max {
+            return Vec::new();
+        }
+
+        let continuous = (0..CONTINUOUS_SAMPLES)
+            .map(|i| min + (max - min) * i as f64 / (CONTINUOUS_SAMPLES - 1) as f64);
+
+        let (first, last) = (min.ceil(), max.floor());
+        let steps = last - first;
+        if stepped && steps >= 0.0 {
+            if steps <= MAX_STEPS {
+                return (0..=steps as usize).map(|i| first + i as f64).collect();
+            }
+            let mut values = continuous
+                .map(|value| value.round().clamp(first, last))
+                .collect::<Vec<_>>();
+            values.dedup();
+            return values;
+        }
+
+        continuous.collect()
+    }
+
+    // The number a text starts with, e.g. -12.5 for "-12.5 dB"
+    fn leading_number(text: &str) -> Option<f64> {
+        let text = text.trim_start();
+        let end = text
+            .char_indices()
+            .find(|(i, c)| !(c.is_ascii_digit() || *c == '.' || (*i == 0 && "+-".contains(*c))))
+            .map(|(i, _)| i)
+            .unwrap_or(text.len());
+        text[..end].parse().ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn samples_every_step_of_small_stepped_ranges() {
+        assert_eq!(
+            InfoParamRoundTrip::sample_range(-0.5, 3.5, true),
+            vec![0.0, 1.0, 2.0, 3.0]
+        );
+    }
+
+    #[test]
+    fn rounds_samples_of_large_stepped_ranges_to_steps() {
+        let values = InfoParamRoundTrip::sample_range(0.0, 10_000.5, true);
+        assert_eq!(values.len(), CONTINUOUS_SAMPLES);
+        assert_eq!(values.first(), Some(&0.0));
+        assert_eq!(values.last(), Some(&10_000.0));
+        assert!(values.iter().all(|value| value.fract() == 0.0));
+    }
+
+    #[test]
+    fn samples_continuous_ranges_evenly() {
+        let values = InfoParamRoundTrip::sample_range(0.0, 1.0, false);
+        assert_eq!(values.len(), CONTINUOUS_SAMPLES);
+        assert_eq!(values[8], 0.5);
+        assert_eq!(values.last(), Some(&1.0));
+    }
+
+    #[test]
+    fn skips_invalid_ranges() {
+        assert!(InfoParamRoundTrip::sample_range(1.0, 0.0, false).is_empty());
+        assert!(InfoParamRoundTrip::sample_range(0.0, f64::INFINITY, true).is_empty());
+        assert!(InfoParamRoundTrip::sample_range(f64::NAN, 1.0, false).is_empty());
+    }
+}
@@ -1,3 +1,6 @@
+use clack_extensions::ambisonic::{
+    AmbisonicConfig, AmbisonicNormalization, AmbisonicOrdering, PluginAmbisonic,
+};
 use clack_extensions::audio_ports::{
     AudioPortFlags, AudioPortInfo, AudioPortInfoBuffer, AudioPortType, PluginAudioPorts,
 };
@@ -7,9 +10,17 @@ use clack_extensions::audio_ports_config::{
 use clack_extensions::note_ports::{
     NoteDialect, NoteDialects, NotePortInfo, NotePortInfoBuffer, PluginNotePorts,
 };
+use clack_extensions::surround::PluginSurround;
 use clack_host::plugin::PluginMainThreadHandle;
 use serde::Serialize;
 use std::ffi::CStr;
+use std::mem::MaybeUninit;
+
+// Names of the CLAP_SURROUND_* channel identifiers, indexed by their value
+const SURROUND_CHANNEL_NAMES: [&str; 18] = [
+    "FL", "FR", "FC", "LFE", "BL", "BR", "FLC", "FRC", "BC", "SL", "SR", "TC", "TFL", "TFC", "TFR",
+    "TBL", "TBC", "TBR",
+];
 
 #[derive(Serialize)]
 #[serde(rename_all = "kebab-case")]
@@ -31,6 +42,8 @@ impl InfoAudioPortsExtension {
 
         if let Some(audio_ports) = plugin.get_extension::<PluginAudioPorts>() {
             implemented = true;
+            let surround = plugin.get_extension::<PluginSurround>();
+            let ambisonic = plugin.get_extension::<PluginAmbisonic>();
 
             // Get input port count
             input_port_count = audio_ports.count(plugin, true);
@@ -40,7 +53,9 @@ impl InfoAudioPortsExtension {
             for i in 0..input_port_count {
                 let mut buffer = AudioPortInfoBuffer::default();
                 if let Some(port_info) = audio_ports.get(plugin, i, true, &mut buffer) {
-                    input_ports.push(InfoAudioPort::from_port_info(&port_info));
+                    let mut port = InfoAudioPort::from_port_info(&port_info);
+                    port.query_layout(plugin, surround, ambisonic, true, i, &port_info);
+                    input_ports.push(port);
                 }
             }
 
@@ -48,7 +63,9 @@ impl InfoAudioPortsExtension {
             for i in 0..output_port_count {
                 let mut buffer = AudioPortInfoBuffer::default();
                 if let Some(port_info) = audio_ports.get(plugin, i, false, &mut buffer) {
-                    output_ports.push(InfoAudioPort::from_port_info(&port_info));
+                    let mut port = InfoAudioPort::from_port_info(&port_info);
+                    port.query_layout(plugin, surround, ambisonic, false, i, &port_info);
+                    output_ports.push(port);
                 }
             }
         }
@@ -61,6 +78,14 @@ impl InfoAudioPortsExtension {
             output_ports,
         }
     }
+
+    pub fn input_ports(&self) -> &[InfoAudioPort] {
+        &self.input_ports
+    }
+
+    pub fn output_ports(&self) -> &[InfoAudioPort] {
+        &self.output_ports
+    }
 }
 
 #[derive(Serialize)]
@@ -153,6 +178,39 @@ pub struct InfoAudioPort {
     flags: InfoAudioPortFlag,
     #[serde(skip_serializing_if = "Option::is_none")]
     in_place_pair: Option<u32>,
+    /// The position of each channel, for surround ports (from clap.surround)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    channel_map: Option<Vec<String>>,
+    /// The channel ordering and normalization, for ambisonic ports (from clap.ambisonic)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    ambisonic: Option<InfoAmbisonicConfig>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct InfoAmbisonicConfig {
+    ordering: String,
+    normalization: String,
+}
+
+impl InfoAmbisonicConfig {
+    fn from_config(config: &AmbisonicConfig) -> Self {
+        Self {
+            ordering: match config.ordering {
+                AmbisonicOrdering::FuMa => "fuma",
+                AmbisonicOrdering::ACN => "acn",
+            }
+            .to_string(),
+            normalization: match config.normalization {
+                AmbisonicNormalization::MaxN => "maxn",
+                AmbisonicNormalization::SN3D => "sn3d",
+                AmbisonicNormalization::N3D => "n3d",
+                AmbisonicNormalization::SN2D => "sn2d",
+                AmbisonicNormalization::N2D => "n2d",
+            }
+            .to_string(),
+        }
+    }
 }
 
 impl InfoAudioPort {
@@ -167,20 +225,66 @@ impl InfoAudioPort {
                 value: port_info.flags.bits(),
             },
             in_place_pair: port_info.in_place_pair.map(Into::into),
+            channel_map: None,
+            ambisonic: None,
         }
     }
 
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn channel_count(&self) -> u32 {
+        self.channel_count
+    }
+
+    pub fn is_main(&self) -> bool {
+        self.flags.value & AudioPortFlags::IS_MAIN.bits() != 0
+    }
+
+    // Ask the surround and ambisonic extensions how the channels of the port are laid out
+    fn query_layout(
+        &mut self,
+        plugin: &mut PluginMainThreadHandle,
+        surround: Option<PluginSurround>,
+        ambisonic: Option<PluginAmbisonic>,
+        is_input: bool,
+        index: u32,
+        port_info: &AudioPortInfo,
+    ) {
+        if let Some(surround) =
+            surround.filter(|_| port_info.port_type == Some(AudioPortType::SURROUND))
+        {
+            let mut buffer = vec![MaybeUninit::uninit(); port_info.channel_count as usize];
+            let channel_map = surround.get_channel_map(plugin, is_input, index, &mut buffer);
+            self.channel_map = Some(
+                channel_map
+                    .iter()
+                    .map(
+                        |&channel| match SURROUND_CHANNEL_NAMES.get(channel as usize) {
+                            Some(name) => name.to_string(),
+                            None => format!("unknown ({})", channel),
+                        },
+                    )
+                    .collect(),
+            );
+        }
+
+        if let Some(ambisonic) =
+            ambisonic.filter(|_| port_info.port_type == Some(AudioPortType::AMBISONIC))
+        {
+            self.ambisonic = ambisonic
+                .get_config(plugin, is_input, index)
+                .map(|config| InfoAmbisonicConfig::from_config(&config));
+        }
+    }
+
+    // The CLAP port type as is (e.g. "mono", "stereo", "surround", "ambisonic" or
+    // a vendor specific type), or "unknown" when the plugin doesn't give one.
     fn port_type_to_string(port_type: Option<AudioPortType>) -> String {
-        if let Some(pt) = port_type {
-            if pt == AudioPortType::MONO {
-                "mono".to_string()
-            } else if pt == AudioPortType::STEREO {
-                "stereo".to_string()
-            } else {
-                "unknown".to_string()
-            }
-        } else {
-            "unknown".to_string()
+        match port_type {
+            Some(port_type) => port_type.0.to_string_lossy().to_string(),
+            None => "unknown".to_string(),
         }
     }
     fn port_flags_to_str_list(flags: AudioPortFlags) -> Option<Vec<&'static str>> {
@@ -294,7 +398,7 @@ impl InfoNotePorts {
 
                         // Convert preferred dialect to string
                         let dialect = match port_info.preferred_dialect {
-                            Some(dialect) => format!("{:?}", dialect).to_lowercase(),
+                            Some(dialect) => Self::dialect_to_str(dialect).to_string(),
                             None => "unknown".to_string(),
                         };
 
@@ -342,7 +446,7 @@ impl InfoNotePorts {
 
                         // Convert preferred dialect to string
                         let dialect = match port_info.preferred_dialect {
-                            Some(dialect) => format!("{:?}", dialect).to_lowercase(),
+                            Some(dialect) => Self::dialect_to_str(dialect).to_string(),
                             None => "unknown".to_string(),
                         };
 
@@ -369,6 +473,16 @@ impl InfoNotePorts {
             output_ports,
         }
     }
+
+    // The same names as in the supported dialects list
+    fn dialect_to_str(dialect: NoteDialect) -> &'static str {
+        match dialect {
+            NoteDialect::Clap => "clap",
+            NoteDialect::Midi => "midi",
+            NoteDialect::MidiMpe => "midi-mpe",
+            NoteDialect::Midi2 => "midi2",
+        }
+    }
 }
 
 #[derive(serde::Serialize)]
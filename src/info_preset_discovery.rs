@@ -0,0 +1,512 @@
+use std::{
+    collections::HashMap,
+    ffi::{CStr, CString},
+    path::{Path, PathBuf},
+};
+
+use clack_extensions::preset_discovery::{
+    FileType, Flags, IndexerImplementation, Location, LocationInfo, MetadataReceiverImpl,
+    PresetDiscoveryFactory, Provider, Soundpack, Timestamp, UniversalPluginId,
+};
+use clack_host::{bundle::PluginBundle, host::HostError, host::HostInfo};
+
+use crate::Watchdog;
+
+// How many directory levels below a declared location to look for preset files
+const MAX_PRESET_DEPTH: usize = 8;
+
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct InfoPresetDiscovery {
+    providers: Vec<InfoPresetProvider>,
+}
+
+#[derive(Clone, Default, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct InfoPresetProvider {
+    id: String,
+    name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    vendor: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+    file_types: Vec<InfoPresetFileType>,
+    locations: Vec<InfoPresetLocation>,
+    soundpacks: Vec<InfoSoundpack>,
+    presets: Vec<InfoPreset>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    metadata_errors: Vec<InfoPresetMetadataError>,
+    /// Declarations that keep presets from being found, e.g. no file type for a directory
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    warnings: Vec<String>,
+}
+
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct InfoPresetFileType {
+    name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    description: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    extension: Option<String>,
+}
+
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct InfoPresetLocation {
+    name: String,
+    flags: Vec<String>,
+    /// The file or directory of the location, or None for presets stored in the plugin
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    path: Option<String>,
+}
+
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct InfoSoundpack {
+    id: String,
+    name: String,
+    flags: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    description: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    homepage_url: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    vendor: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    image_path: Option<String>,
+}
+
+#[derive(Clone, Default, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct InfoPreset {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    name: Option<String>,
+    /// The preset file, or None for presets stored in the plugin
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    path: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    load_key: Option<String>,
+    plugin_ids: Vec<InfoUniversalPluginId>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    soundpack_id: Option<String>,
+    flags: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    creators: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    description: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    features: Vec<String>,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    extra_info: HashMap<String, String>,
+}
+
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct InfoUniversalPluginId {
+    abi: String,
+    id: String,
+}
+
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct InfoPresetMetadataError {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    path: Option<String>,
+    os_error: i32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    message: Option<String>,
+}
+
+impl InfoPresetDiscovery {
+    /// Index every preset provider of the bundle, or None when the bundle doesn't
+    /// export a preset-discovery factory. Each provider is indexed in its own
+    /// `preset-provider` phase of the watchdog.
+    pub fn from_bundle(
+        bundle: &PluginBundle,
+        host_info: &HostInfo,
+        watchdog: &Watchdog,
+    ) -> Option<Self> {
+        let factory = bundle.get_factory::<PresetDiscoveryFactory>()?;
+        let providers = (0..factory.provider_count())
+            .filter_map(|index| factory.provider_descriptor(index))
+            .map(|descriptor| {
+                watchdog.enter("preset-provider");
+                let mut provider = InfoPresetProvider {
+                    id: to_string(descriptor.id()).unwrap_or_default(),
+                    name: to_string(descriptor.name()).unwrap_or_default(),
+                    vendor: to_string(descriptor.vendor()),
+                    ..InfoPresetProvider::default()
+                };
+                match descriptor.id() {
+                    Some(id) => provider.index(bundle, host_info, id),
+                    None => provider.error = Some("Missing provider id".to_string()),
+                }
+                provider
+            })
+            .collect();
+        Some(Self { providers })
+    }
+}
+
+impl InfoPresetProvider {
+    // Create the provider, let it declare its file types, locations and soundpacks,
+    // then read the metadata of every preset in those locations.
+    fn index(&mut self, bundle: &PluginBundle, host_info: &HostInfo, provider_id: &CStr) {
+        let indexer = PresetIndexer::default();
+        let mut provider = match Provider::instantiate(indexer, bundle, provider_id, host_info) {
+            Ok(provider) => provider,
+            Err(err) => {
+                self.error = Some(format!("Failed to create the preset provider: {}", err));
+                return;
+            }
+        };
+
+        let indexer = provider.indexer();
+        self.file_types = indexer.file_types.clone();
+        self.soundpacks = indexer.soundpacks.clone();
+        self.locations = indexer
+            .locations
+            .iter()
+            .map(|(location, _)| location.clone())
+            .collect();
+        let locations = indexer.locations.clone();
+
+        for (location, path) in locations {
+            let Some(path) = path else {
+                self.read_metadata(&mut provider, None);
+                continue;
+            };
+            if path.is_dir() && self.file_types.is_empty() {
+                self.warnings.push(format!(
+                    "Location `{}` is a directory but no file type was declared: \
+                     no preset file can be found in it",
+                    location.name
+                ));
+                continue;
+            }
+            for file in self.preset_files(&path) {
+                self.read_metadata(&mut provider, Some(&file));
+            }
+        }
+    }
+
+    fn read_metadata(&mut self, provider: &mut Provider<PresetIndexer>, path: Option<&Path>) {
+        let display_path = path.map(|path| path.display().to_string());
+        let c_path = match path.map(|path| CString::new(path.to_string_lossy().as_bytes())) {
+            Some(Ok(c_path)) => Some(c_path),
+            Some(Err(_)) => return,
+            None => None,
+        };
+        let location = match &c_path {
+            Some(c_path) => Location::File { path: c_path },
+            None => Location::Plugin,
+        };
+
+        let mut receiver = PresetReceiver::default();
+        provider.get_metadata(location, &mut receiver);
+
+        for mut preset in receiver.presets {
+            preset.path = display_path.clone();
+            self.presets.push(preset);
+        }
+        self.metadata_errors
+            .extend(receiver.errors.into_iter().map(|(os_error, message)| {
+                InfoPresetMetadataError {
+                    path: display_path.clone(),
+                    os_error,
+                    message,
+                }
+            }));
+    }
+
+    // The files of a location matching the declared file types: the location itself
+    // when it's a file, or the files found below it when it's a directory.
+    fn preset_files(&self, path: &Path) -> Vec<PathBuf> {
+        if !path.is_dir() {
+            return vec![path.to_path_buf()];
+        }
+
+        // A file type without extension means any file can be a preset
+        let extensions = self
+            .file_types
+            .iter()
+            .map(|file_type| file_type.extension.as_deref())
+            .collect::<Option<Vec<_>>>();
+
+        let mut files = Vec::new();
+        let mut pending = vec![(path.to_path_buf(), 0)];
+        while let Some((dir, depth)) = pending.pop() {
+            let Ok(entries) = std::fs::read_dir(&dir) else {
+                continue;
+            };
+            for entry in entries.flatten() {
+                let path = entry.path();
+                if path.is_dir() {
+                    if depth < MAX_PRESET_DEPTH {
+                        pending.push((path, depth + 1));
+                    }
+                    continue;
+                }
+                let matches = match &extensions {
+                    Some(extensions) => path.extension().is_some_and(|extension| {
+                        extensions
+                            .iter()
+                            .any(|expected| extension.eq_ignore_ascii_case(expected))
+                    }),
+                    None => true,
+                };
+                if matches {
+                    files.push(path);
+                }
+            }
+        }
+        files.sort();
+        files
+    }
+}
+
+// Collects what the provider declares while it is being initialized
+#[derive(Default)]
+struct PresetIndexer {
+    file_types: Vec<InfoPresetFileType>,
+    // Each location along with its path, if it's a file location
+    locations: Vec<(InfoPresetLocation, Option<PathBuf>)>,
+    soundpacks: Vec<InfoSoundpack>,
+}
+
+impl IndexerImplementation for PresetIndexer {
+    fn declare_filetype(&mut self, file_type: FileType) -> Result<(), HostError> {
+        self.file_types.push(InfoPresetFileType {
+            name: file_type.name.to_string_lossy().to_string(),
+            description: to_string(file_type.description),
+            extension: to_string(file_type.file_extension),
+        });
+        Ok(())
+    }
+
+    fn declare_location(&mut self, location: LocationInfo) -> Result<(), HostError> {
+        let path = match location.location {
+            Location::File { path } => Some(PathBuf::from(path.to_string_lossy().to_string())),
+            Location::Plugin => None,
+        };
+        self.locations.push((
+            InfoPresetLocation {
+                name: location.name.to_string_lossy().to_string(),
+                flags: flags_to_strings(location.flags),
+                path: path.as_ref().map(|path| path.display().to_string()),
+            },
+            path,
+        ));
+        Ok(())
+    }
+
+    fn declare_soundpack(&mut self, soundpack: Soundpack) -> Result<(), HostError> {
+        self.soundpacks.push(InfoSoundpack {
+            id: soundpack.id.to_string_lossy().to_string(),
+            name: soundpack.name.to_string_lossy().to_string(),
+            flags: flags_to_strings(soundpack.flags),
+            description: to_string(soundpack.description),
+            homepage_url: to_string(soundpack.homepage_url),
+            vendor: to_string(soundpack.vendor),
+            image_path: to_string(soundpack.image_path),
+        });
+        Ok(())
+    }
+}
+
+// Collects the presets of a single location
+#[derive(Default)]
+struct PresetReceiver {
+    presets: Vec<InfoPreset>,
+    errors: Vec<(i32, Option<String>)>,
+}
+
+impl PresetReceiver {
+    fn current(&mut self) -> Option<&mut InfoPreset> {
+        self.presets.last_mut()
+    }
+}
+
+impl MetadataReceiverImpl for PresetReceiver {
+    fn on_error(&mut self, os_error: i32, error_message: Option<&CStr>) {
+        self.errors.push((os_error, to_string(error_message)));
+    }
+
+    fn begin_preset(
+        &mut self,
+        name: Option<&CStr>,
+        load_key: Option<&CStr>,
+    ) -> Result<(), HostError> {
+        self.presets.push(InfoPreset {
+            name: to_string(name),
+            load_key: to_string(load_key),
+            ..InfoPreset::default()
+        });
+        Ok(())
+    }
+
+    fn add_plugin_id(&mut self, plugin_id: UniversalPluginId) {
+        if let Some(preset) = self.current() {
+            preset.plugin_ids.push(InfoUniversalPluginId {
+                abi: plugin_id.abi.to_string_lossy().to_string(),
+                id: plugin_id.id.to_string_lossy().to_string(),
+            });
+        }
+    }
+
+    fn set_soundpack_id(&mut self, soundpack_id: &CStr) {
+        if let Some(preset) = self.current() {
+            preset.soundpack_id = to_string(Some(soundpack_id));
+        }
+    }
+
+    fn set_flags(&mut self, flags: Flags) {
+        if let Some(preset) = self.current() {
+            preset.flags = flags_to_strings(flags);
+        }
+    }
+
+    fn add_creator(&mut self, creator: &CStr) {
+        if let Some(preset) = self.current() {
+            preset.creators.push(creator.to_string_lossy().to_string());
+        }
+    }
+
+    fn set_description(&mut self, description: &CStr) {
+        if let Some(preset) = self.current() {
+            preset.description = to_string(Some(description));
+        }
+    }
+
+    fn set_timestamps(
+        &mut self,
+        _creation_time: Option<Timestamp>,
+        _modification_time: Option<Timestamp>,
+    ) {
+    }
+
+    fn add_feature(&mut self, feature: &CStr) {
+        if let Some(preset) = self.current() {
+            preset.features.push(feature.to_string_lossy().to_string());
+        }
+    }
+
+    fn add_extra_info(&mut self, key: &CStr, value: &CStr) {
+        if let Some(preset) = self.current() {
+            preset.extra_info.insert(
+                key.to_string_lossy().to_string(),
+                value.to_string_lossy().to_string(),
+            );
+        }
+    }
+}
+
+fn to_string(s: Option<&CStr>) -> Option<String> {
+    s.map(|s| s.to_string_lossy().to_string())
+}
+
+fn flags_to_strings(flags: Flags) -> Vec<String> {
+    let names = [
+        (Flags::IS_FACTORY_CONTENT, "factory-content"),
+        (Flags::IS_USER_CONTENT, "user-content"),
+        (Flags::IS_DEMO_CONTENT, "demo-content"),
+        (Flags::IS_FAVORITE, "favorite"),
+    ];
+    names
+        .into_iter()
+        .filter(|(flag, _)| flags.contains(*flag))
+        .map(|(_, name)| name.to_string())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::temp_dir;
+
+    fn provider(extensions: &[Option<&str>]) -> InfoPresetProvider {
+        InfoPresetProvider {
+            file_types: extensions
+                .iter()
+                .map(|extension| InfoPresetFileType {
+                    name: "preset".to_string(),
+                    description: None,
+                    extension: extension.map(str::to_string),
+                })
+                .collect(),
+            ..InfoPresetProvider::default()
+        }
+    }
+
+    fn create_files(root: &Path, files: &[&str]) {
+        for file in files {
+            let path = root.join(file);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, b"").unwrap();
+        }
+    }
+
+    #[test]
+    fn finds_files_with_a_declared_extension_below_a_directory() {
+        let root = temp_dir("preset-extensions");
+        create_files(
+            &root,
+            &[
+                "a.fxp",
+                "b.FXP",
+                "c.txt",
+                "bank/d.vstpreset",
+                "bank/e",
+                "bank/f.fxp",
+            ],
+        );
+
+        let files = provider(&[Some("fxp"), Some("vstpreset")]).preset_files(&root);
+        let expected = ["a.fxp", "b.FXP", "bank/d.vstpreset", "bank/f.fxp"];
+        assert_eq!(files, expected.map(|file| root.join(file)));
+
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn finds_any_file_when_a_file_type_has_no_extension() {
+        let root = temp_dir("preset-any-file");
+        create_files(&root, &["a.fxp", "bank/b"]);
+
+        let files = provider(&[Some("fxp"), None]).preset_files(&root);
+        assert_eq!(files, [root.join("a.fxp"), root.join("bank/b")]);
+
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn stops_below_the_maximum_depth() {
+        let root = temp_dir("preset-depth");
+        let deepest = (0..MAX_PRESET_DEPTH).fold(PathBuf::new(), |dir, _| dir.join("d"));
+        let too_deep = deepest.join("d");
+        create_files(
+            &root,
+            &[
+                deepest.join("a.fxp").to_str().unwrap(),
+                too_deep.join("b.fxp").to_str().unwrap(),
+            ],
+        );
+
+        let files = provider(&[Some("fxp")]).preset_files(&root);
+        assert_eq!(files, [root.join(deepest).join("a.fxp")]);
+
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn keeps_a_file_location_as_is() {
+        let root = temp_dir("preset-file-location");
+        create_files(&root, &["a.txt"]);
+
+        let files = provider(&[Some("fxp")]).preset_files(&root.join("a.txt"));
+        assert_eq!(files, [root.join("a.txt")]);
+
+        std::fs::remove_dir_all(root).unwrap();
+    }
+}
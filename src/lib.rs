@@ -1,13 +1,37 @@
+mod activation;
 mod clap_info_host;
 mod clap_scanner;
+mod host_callbacks;
+mod host_extensions;
 mod info;
 mod info_other_extensions;
 mod info_params;
+mod info_params_roundtrip;
 mod info_ports;
+mod info_preset_discovery;
+mod lifecycle;
+mod plugin_log;
+mod plugin_matcher;
+mod render;
+mod scan_cache;
+#[cfg(test)]
+mod test_utils;
+mod watchdog;
 
+pub use activation::*;
 pub use clap_info_host::*;
 pub use clap_scanner::*;
+pub use host_callbacks::*;
+pub use host_extensions::*;
 pub use info::*;
 pub use info_other_extensions::*;
 pub use info_params::*;
+pub use info_params_roundtrip::*;
 pub use info_ports::*;
+pub use info_preset_discovery::*;
+pub use lifecycle::*;
+pub use plugin_log::*;
+pub use plugin_matcher::*;
+pub use render::*;
+pub use scan_cache::*;
+pub use watchdog::*;
@@ -0,0 +1,220 @@
+use std::{
+    any::Any,
+    fmt::Display,
+    panic::AssertUnwindSafe,
+    time::{Duration, Instant},
+};
+
+/// The lifecycle steps a plugin instance went through while being queried.
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct InfoLifecycle {
+    steps: Vec<InfoLifecycleStep>,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct InfoLifecycleStep {
+    /// One of init, activate, start-processing, stop-processing, deactivate or destroy
+    step: String,
+    duration_ms: f64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+impl InfoLifecycle {
+    /// Run a step, recording how long it took and its error, if any.
+    pub fn run<T, E: Display>(
+        &mut self,
+        step: &'static str,
+        f: impl FnOnce() -> Result<T, E>,
+    ) -> Result<T, E> {
+        let started_at = Instant::now();
+        let result = f();
+        let error = result.as_ref().err().map(|err| err.to_string());
+        self.record(step, started_at.elapsed(), error);
+        result
+    }
+
+    /// Run a step that has no error to report (like deactivate or destroy), recording
+    /// a panic as its error instead of propagating it.
+    pub fn run_catching_panic(&mut self, step: &'static str, f: impl FnOnce()) {
+        let _ = self.run(step, || {
+            std::panic::catch_unwind(AssertUnwindSafe(f)).map_err(|panic| panic_message(&*panic))
+        });
+    }
+
+    /// Record a step run elsewhere (e.g. on the audio thread).
+    pub fn record(&mut self, step: &'static str, duration: Duration, error: Option<String>) {
+        self.steps.push(InfoLifecycleStep {
+            step: step.to_string(),
+            duration_ms: duration.as_secs_f64() * 1000.0,
+            error,
+        });
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.steps.is_empty()
+    }
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct InfoLifecycleStress {
+    cycles: usize,
+    /// activate -> deactivate cycles on a single instance
+    activate_deactivate: InfoStressCycles,
+    /// instantiate -> init -> destroy cycles
+    instantiate_destroy: InfoStressCycles,
+}
+
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct InfoStressCycles {
+    completed: usize,
+    duration_ms: f64,
+    /// How much the resident memory of the process grew over the cycles (Linux only)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    rss_growth_kb: Option<i64>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    failures: Vec<InfoStressFailure>,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct InfoStressFailure {
+    cycle: usize,
+    error: String,
+}
+
+impl InfoLifecycleStress {
+    pub fn new(
+        cycles: usize,
+        activate_deactivate: InfoStressCycles,
+        instantiate_destroy: InfoStressCycles,
+    ) -> Self {
+        Self {
+            cycles,
+            activate_deactivate,
+            instantiate_destroy,
+        }
+    }
+}
+
+impl InfoStressCycles {
+    /// Run the given cycle up to `cycles` times, stopping at the first failing one.
+    pub fn run<E: Display>(cycles: usize, mut cycle: impl FnMut() -> Result<(), E>) -> Self {
+        let rss_before = resident_memory_kb();
+        let started_at = Instant::now();
+        let mut result = Self::default();
+        for index in 0..cycles {
+            match cycle() {
+                Ok(()) => result.completed += 1,
+                Err(err) => {
+                    result.failures.push(InfoStressFailure {
+                        cycle: index,
+                        error: err.to_string(),
+                    });
+                    break;
+                }
+            }
+        }
+        result.duration_ms = started_at.elapsed().as_secs_f64() * 1000.0;
+        result.rss_growth_kb = rss_before
+            .zip(resident_memory_kb())
+            .map(|(before, after)| after as i64 - before as i64);
+        result
+    }
+
+    /// Cycles that couldn't start, e.g. because the instance to cycle couldn't be created.
+    pub fn not_started(error: impl Display) -> Self {
+        Self {
+            failures: vec![InfoStressFailure {
+                cycle: 0,
+                error: error.to_string(),
+            }],
+            ..Self::default()
+        }
+    }
+}
+
+fn panic_message(panic: &(dyn Any + Send)) -> String {
+    let message = panic
+        .downcast_ref::<&str>()
+        .copied()
+        .or_else(|| panic.downcast_ref::<String>().map(String::as_str))
+        .unwrap_or("unknown panic payload");
+    format!("Panicked: {}", message)
+}
+
+// The resident memory of the process in kB, from /proc/self/status (Linux only)
+fn resident_memory_kb() -> Option<u64> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    status
+        .lines()
+        .find_map(|line| line.strip_prefix("VmRSS:"))
+        .and_then(|value| value.trim().trim_end_matches("kB").trim().parse().ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn errors(lifecycle: &InfoLifecycle) -> Vec<(&str, Option<&str>)> {
+        lifecycle
+            .steps
+            .iter()
+            .map(|step| (step.step.as_str(), step.error.as_deref()))
+            .collect()
+    }
+
+    #[test]
+    fn records_steps_and_their_errors() {
+        let mut lifecycle = InfoLifecycle::default();
+        assert_eq!(lifecycle.run("init", || Ok::<_, String>(1)), Ok(1));
+        assert!(lifecycle.run("activate", || Err::<(), _>("no")).is_err());
+
+        assert_eq!(
+            errors(&lifecycle),
+            [("init", None), ("activate", Some("no"))]
+        );
+    }
+
+    #[test]
+    fn records_panics_as_errors() {
+        let mut lifecycle = InfoLifecycle::default();
+        lifecycle.run_catching_panic("deactivate", || {});
+        lifecycle.run_catching_panic("destroy", || panic!("dropped twice"));
+
+        assert_eq!(
+            errors(&lifecycle),
+            [
+                ("deactivate", None),
+                ("destroy", Some("Panicked: dropped twice"))
+            ]
+        );
+    }
+
+    #[test]
+    fn stops_stress_cycles_at_the_first_failure() {
+        let mut count = 0;
+        let cycles = InfoStressCycles::run(10, || {
+            count += 1;
+            if count == 3 { Err("failed") } else { Ok(()) }
+        });
+
+        assert_eq!(count, 3);
+        assert_eq!(cycles.completed, 2);
+        assert_eq!(cycles.failures.len(), 1);
+        assert_eq!(cycles.failures[0].cycle, 2);
+    }
+
+    #[test]
+    fn records_cycles_that_did_not_start() {
+        let cycles = InfoStressCycles::not_started("Failed to instantiate plugin");
+
+        assert_eq!(cycles.completed, 0);
+        assert_eq!(cycles.failures[0].cycle, 0);
+        assert_eq!(cycles.failures[0].error, "Failed to instantiate plugin");
+    }
+}
@@ -0,0 +1,113 @@
+use std::{str::FromStr, sync::Mutex};
+
+use clack_extensions::log::LogSeverity;
+
+use crate::Watchdog;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LogLevel {
+    #[default]
+    Debug,
+    Info,
+    Warning,
+    Error,
+    Fatal,
+}
+
+impl LogLevel {
+    // Misbehaving reports are errors on the plugin or host side
+    fn from_severity(severity: LogSeverity) -> Self {
+        match severity {
+            LogSeverity::Debug => LogLevel::Debug,
+            LogSeverity::Info => LogLevel::Info,
+            LogSeverity::Warning => LogLevel::Warning,
+            LogSeverity::Error | LogSeverity::HostMisbehaving | LogSeverity::PluginMisbehaving => {
+                LogLevel::Error
+            }
+            LogSeverity::Fatal => LogLevel::Fatal,
+        }
+    }
+}
+
+impl FromStr for LogLevel {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "debug" => Ok(LogLevel::Debug),
+            "info" => Ok(LogLevel::Info),
+            "warning" => Ok(LogLevel::Warning),
+            "error" => Ok(LogLevel::Error),
+            "fatal" => Ok(LogLevel::Fatal),
+            _ => Err(format!(
+                "expected one of debug, info, warning, error or fatal, got `{}`",
+                value
+            )),
+        }
+    }
+}
+
+/// A message the plugin sent through the clap.log host extension.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct InfoLogMessage {
+    severity: String,
+    /// The lifecycle phase or extension probe during which the message was sent
+    phase: String,
+    message: String,
+}
+
+/// Collects the log messages of a plugin instance, tagged with the current watchdog phase.
+#[derive(Debug)]
+pub struct PluginLog {
+    watchdog: Watchdog,
+    level: LogLevel,
+    to_stderr: bool,
+    messages: Mutex<Vec<InfoLogMessage>>,
+}
+
+impl PluginLog {
+    pub fn new(watchdog: Watchdog, level: LogLevel, to_stderr: bool) -> Self {
+        Self {
+            watchdog,
+            level,
+            to_stderr,
+            messages: Mutex::new(Vec::new()),
+        }
+    }
+
+    pub fn log(&self, severity: LogSeverity, message: &str) {
+        if LogLevel::from_severity(severity) < self.level {
+            return;
+        }
+
+        let message = InfoLogMessage {
+            severity: Self::severity_name(severity).to_string(),
+            phase: self.watchdog.phase().to_string(),
+            message: message.to_string(),
+        };
+        if self.to_stderr {
+            eprintln!(
+                "[{}] ({}) {}",
+                message.severity, message.phase, message.message
+            );
+        }
+        self.messages.lock().unwrap().push(message);
+    }
+
+    pub fn messages(&self) -> Vec<InfoLogMessage> {
+        self.messages.lock().unwrap().clone()
+    }
+
+    fn severity_name(severity: LogSeverity) -> &'static str {
+        match severity {
+            LogSeverity::Debug => "debug",
+            LogSeverity::Info => "info",
+            LogSeverity::Warning => "warning",
+            LogSeverity::Error => "error",
+            LogSeverity::Fatal => "fatal",
+            LogSeverity::HostMisbehaving => "host-misbehaving",
+            LogSeverity::PluginMisbehaving => "plugin-misbehaving",
+        }
+    }
+}
@@ -0,0 +1,113 @@
+use std::fmt;
+
+/// Selects plugins in a bundle by id or name, rather than by factory index.
+#[derive(Debug, Clone)]
+pub enum PluginMatcher {
+    Id(String),
+    Glob(String),
+    Regex(regex::Regex),
+}
+
+impl PluginMatcher {
+    pub fn regex(pattern: &str) -> Result<Self, regex::Error> {
+        regex::Regex::new(pattern).map(PluginMatcher::Regex)
+    }
+
+    pub fn matches(&self, id: &str, name: &str) -> bool {
+        match self {
+            PluginMatcher::Id(plugin_id) => plugin_id == id,
+            PluginMatcher::Glob(pattern) => {
+                glob_matches(pattern, id) || glob_matches(pattern, name)
+            }
+            PluginMatcher::Regex(regex) => regex.is_match(id) || regex.is_match(name),
+        }
+    }
+}
+
+impl fmt::Display for PluginMatcher {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PluginMatcher::Id(id) => write!(f, "id `{}`", id),
+            PluginMatcher::Glob(pattern) => write!(f, "pattern `{}`", pattern),
+            PluginMatcher::Regex(regex) => write!(f, "regex `{}`", regex.as_str()),
+        }
+    }
+}
+
+// Match a whole string against a glob pattern where `*` matches any sequence of
+// characters and `?` matches a single one.
+fn glob_matches(pattern: &str, text: &str) -> bool {
+    let pattern = pattern.chars().collect::<Vec<_>>();
+    let text = text.chars().collect::<Vec<_>>();
+
+    let (mut p, mut t) = (0, 0);
+    // Position of the last `*` in the pattern, and of the text when we met it
+    let mut backtrack = None;
+
+    while t < text.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == text[t]) {
+            p += 1;
+            t += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            backtrack = Some((p, t));
+            p += 1;
+        } else if let Some((star_p, star_t)) = backtrack {
+            // Let the last `*` swallow one more character
+            p = star_p + 1;
+            t = star_t + 1;
+            backtrack = Some((star_p, star_t + 1));
+        } else {
+            return false;
+        }
+    }
+
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn star_matches_any_sequence() {
+        assert!(glob_matches("com.vendor.*", "com.vendor.reverb"));
+        assert!(glob_matches("com.vendor.*", "com.vendor."));
+        assert!(glob_matches("*verb", "com.vendor.reverb"));
+        assert!(glob_matches("*.*.*", "com.vendor.reverb"));
+        assert!(glob_matches("*", ""));
+        assert!(!glob_matches("com.other.*", "com.vendor.reverb"));
+    }
+
+    #[test]
+    fn question_mark_matches_a_single_character() {
+        assert!(glob_matches("Synth ?", "Synth 1"));
+        assert!(glob_matches("Synth ?", "Synth é"));
+        assert!(!glob_matches("Synth ?", "Synth "));
+        assert!(!glob_matches("Synth ?", "Synth 10"));
+    }
+
+    #[test]
+    fn empty_pattern_only_matches_empty_text() {
+        assert!(glob_matches("", ""));
+        assert!(!glob_matches("", "reverb"));
+    }
+
+    #[test]
+    fn patterns_are_anchored() {
+        assert!(!glob_matches("verb", "reverb"));
+        assert!(!glob_matches("rev", "reverb"));
+        assert!(!glob_matches("reverb", "reverb 2"));
+        assert!(glob_matches("reverb", "reverb"));
+    }
+
+    #[test]
+    fn matches_id_or_name() {
+        let matcher = PluginMatcher::Glob("*Reverb".to_string());
+        assert!(matcher.matches("com.vendor.hall", "Hall Reverb"));
+        assert!(!matcher.matches("com.vendor.hall", "Hall"));
+
+        let matcher = PluginMatcher::Id("com.vendor.hall".to_string());
+        assert!(matcher.matches("com.vendor.hall", "Hall"));
+        assert!(!matcher.matches("com.vendor", "com.vendor.hall"));
+    }
+}
@@ -0,0 +1,447 @@
+use std::{
+    fmt::Display,
+    fs::File,
+    io::{BufWriter, ErrorKind, Write},
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use crate::{ActivationSettings, InfoLifecycle, InfoLogMessage};
+
+// Amplitude of the sine and noise signals (-6 dBFS), leaving headroom for gain
+const SIGNAL_AMPLITUDE: f32 = 0.5;
+
+// WAVE_FORMAT_* codes of the fmt chunk
+const WAVE_FORMAT_PCM: u16 = 1;
+const WAVE_FORMAT_IEEE_FLOAT: u16 = 3;
+const WAVE_FORMAT_EXTENSIBLE: u16 = 0xFFFE;
+
+/// The signal `render` feeds to the plugin's main input port, as given on the command line.
+#[derive(Debug, Clone, PartialEq)]
+pub enum RenderInput {
+    Silence,
+    Impulse,
+    Sine,
+    /// Seeded white noise, so renders can be compared between runs
+    Noise,
+    Wav(PathBuf),
+}
+
+impl FromStr for RenderInput {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "silence" => Ok(RenderInput::Silence),
+            "impulse" => Ok(RenderInput::Impulse),
+            "sine" => Ok(RenderInput::Sine),
+            "noise" => Ok(RenderInput::Noise),
+            _ if value.to_lowercase().ends_with(".wav") => Ok(RenderInput::Wav(value.into())),
+            _ => Err(format!(
+                "expected silence, impulse, sine, noise or the path of a .wav file, got `{}`",
+                value
+            )),
+        }
+    }
+}
+
+impl Display for RenderInput {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RenderInput::Silence => write!(f, "silence"),
+            RenderInput::Impulse => write!(f, "impulse"),
+            RenderInput::Sine => write!(f, "sine"),
+            RenderInput::Noise => write!(f, "noise"),
+            RenderInput::Wav(path) => write!(f, "{}", path.display()),
+        }
+    }
+}
+
+/// The input signal, with the WAV file already read.
+#[derive(Debug, Clone)]
+pub enum InputSignal {
+    Silence,
+    Impulse,
+    Sine { frequency: f32 },
+    Noise,
+    Wav(WavAudio),
+}
+
+impl InputSignal {
+    pub fn load(input: &RenderInput, frequency: f32) -> Result<Self, WavError> {
+        Ok(match input {
+            RenderInput::Silence => InputSignal::Silence,
+            RenderInput::Impulse => InputSignal::Impulse,
+            RenderInput::Sine => InputSignal::Sine { frequency },
+            RenderInput::Noise => InputSignal::Noise,
+            RenderInput::Wav(path) => InputSignal::Wav(WavAudio::read(path)?),
+        })
+    }
+
+    fn name(&self) -> String {
+        match self {
+            InputSignal::Silence => "silence".to_string(),
+            InputSignal::Impulse => "impulse".to_string(),
+            InputSignal::Sine { frequency } => format!("sine ({} Hz)", frequency),
+            InputSignal::Noise => "noise".to_string(),
+            InputSignal::Wav(_) => "wav".to_string(),
+        }
+    }
+
+    /// `channel_count` channels of `frames` samples each. The WAV channels are repeated
+    /// (e.g. mono to stereo) or dropped to match, and padded with silence.
+    pub(crate) fn channels(
+        &self,
+        channel_count: usize,
+        frames: usize,
+        sample_rate: f64,
+    ) -> Vec<Vec<f32>> {
+        let mut noise_state = 0x2545_F491_u32;
+        (0..channel_count)
+            .map(|channel| {
+                let mut samples = vec![0.0; frames];
+                match self {
+                    InputSignal::Silence => {}
+                    InputSignal::Impulse => {
+                        if let Some(first) = samples.first_mut() {
+                            *first = 1.0;
+                        }
+                    }
+                    InputSignal::Sine { frequency } => {
+                        let step = std::f64::consts::TAU * *frequency as f64 / sample_rate;
+                        for (frame, sample) in samples.iter_mut().enumerate() {
+                            *sample = SIGNAL_AMPLITUDE * (step * frame as f64).sin() as f32;
+                        }
+                    }
+                    InputSignal::Noise => {
+                        for sample in samples.iter_mut() {
+                            // xorshift32
+                            noise_state ^= noise_state << 13;
+                            noise_state ^= noise_state >> 17;
+                            noise_state ^= noise_state << 5;
+                            let unit = noise_state as f32 / u32::MAX as f32;
+                            *sample = SIGNAL_AMPLITUDE * (unit * 2.0 - 1.0);
+                        }
+                    }
+                    InputSignal::Wav(wav) => {
+                        if !wav.channels.is_empty() {
+                            let source = &wav.channels[channel % wav.channels.len()];
+                            let len = source.len().min(frames);
+                            samples[..len].copy_from_slice(&source[..len]);
+                        }
+                    }
+                }
+                samples
+            })
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RenderSettings {
+    pub input: InputSignal,
+    pub seconds: f64,
+    pub sample_rate: f64,
+    /// The number of frames per process call (the last one may be shorter)
+    pub block_size: u32,
+}
+
+impl RenderSettings {
+    pub fn frames(&self) -> usize {
+        (self.seconds * self.sample_rate).round() as usize
+    }
+
+    /// Whether the sample rate is a whole, positive number of Hz that fits a WAV file.
+    pub fn has_whole_sample_rate(&self) -> bool {
+        self.sample_rate.fract() == 0.0 && (1.0..=u32::MAX as f64).contains(&self.sample_rate)
+    }
+
+    pub fn activation(&self) -> ActivationSettings {
+        ActivationSettings {
+            sample_rate: self.sample_rate,
+            min_frames: 1,
+            max_frames: self.block_size,
+        }
+    }
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct InfoRender {
+    plugin_id: String,
+    input: String,
+    sample_rate: f64,
+    block_size: u32,
+    frames: usize,
+    output_ports: Vec<InfoRenderPort>,
+    lifecycle: InfoLifecycle,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    log: Vec<InfoLogMessage>,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct InfoRenderPort {
+    index: usize,
+    name: String,
+    channel_count: usize,
+    /// The written WAV file (ports without channels aren't written)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    path: Option<String>,
+    peak: f32,
+    rms: f32,
+}
+
+impl InfoRender {
+    pub fn new(
+        plugin_id: String,
+        settings: &RenderSettings,
+        lifecycle: InfoLifecycle,
+        log: Vec<InfoLogMessage>,
+    ) -> Self {
+        Self {
+            plugin_id,
+            input: settings.input.name(),
+            sample_rate: settings.sample_rate,
+            block_size: settings.block_size,
+            frames: settings.frames(),
+            output_ports: Vec::new(),
+            lifecycle,
+            log,
+        }
+    }
+
+    pub fn add_output_port(&mut self, name: String, channels: &[Vec<f32>], path: Option<&Path>) {
+        let samples = channels.iter().flatten();
+        let peak = samples
+            .clone()
+            .fold(0.0_f32, |peak, sample| peak.max(sample.abs()));
+        let (sum, count) = samples.fold((0.0_f64, 0_usize), |(sum, count), sample| {
+            (sum + (*sample as f64).powi(2), count + 1)
+        });
+        self.output_ports.push(InfoRenderPort {
+            index: self.output_ports.len(),
+            name,
+            channel_count: channels.len(),
+            path: path.map(|path| path.display().to_string()),
+            peak,
+            rms: if count == 0 {
+                0.0
+            } else {
+                (sum / count as f64).sqrt() as f32
+            },
+        });
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum WavError {
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+
+    #[error("not a RIFF/WAVE file")]
+    NotWave,
+
+    #[error("missing {0} chunk")]
+    MissingChunk(&'static str),
+
+    #[error("unsupported sample format {format} with {bits} bits per sample")]
+    UnsupportedFormat { format: u16, bits: u16 },
+}
+
+/// Deinterleaved audio, read from or written to a WAV file.
+#[derive(Debug, Clone)]
+pub struct WavAudio {
+    pub sample_rate: u32,
+    pub channels: Vec<Vec<f32>>,
+}
+
+impl WavAudio {
+    pub fn duration_secs(&self) -> f64 {
+        let frames = self.channels.first().map_or(0, Vec::len);
+        frames as f64 / self.sample_rate as f64
+    }
+
+    /// Read a 16, 24 or 32 bits integer, or a 32 or 64 bits float WAV file.
+    pub fn read(path: &Path) -> Result<Self, WavError> {
+        let bytes = std::fs::read(path)?;
+        if bytes.len() < 12 || &bytes[0..4] != b"RIFF" || &bytes[8..12] != b"WAVE" {
+            return Err(WavError::NotWave);
+        }
+
+        let mut format = None;
+        let mut data = None;
+        let mut chunks = &bytes[12..];
+        while chunks.len() >= 8 {
+            let id = &chunks[0..4];
+            let size = u32::from_le_bytes([chunks[4], chunks[5], chunks[6], chunks[7]]) as usize;
+            let body = &chunks[8..chunks.len().min(8 + size)];
+            match id {
+                b"fmt " if body.len() >= 16 => format = Some(body),
+                b"data" => data = Some(body),
+                _ => {}
+            }
+            // Chunks are padded to an even size
+            let next = (8 + size + size % 2).min(chunks.len());
+            chunks = &chunks[next..];
+        }
+
+        let format = format.ok_or(WavError::MissingChunk("fmt"))?;
+        let data = data.ok_or(WavError::MissingChunk("data"))?;
+        let read_u16 = |offset: usize| u16::from_le_bytes([format[offset], format[offset + 1]]);
+        let mut format_code = read_u16(0);
+        let channel_count = read_u16(2) as usize;
+        let sample_rate = u32::from_le_bytes([format[4], format[5], format[6], format[7]]);
+        let bits = read_u16(14);
+        // The actual format of WAVE_FORMAT_EXTENSIBLE is the start of its sub format guid
+        if format_code == WAVE_FORMAT_EXTENSIBLE && format.len() >= 26 {
+            format_code = read_u16(24);
+        }
+
+        let decode: fn(&[u8]) -> f32 = match (format_code, bits) {
+            (WAVE_FORMAT_PCM, 16) => |b| i16::from_le_bytes([b[0], b[1]]) as f32 / 32_768.0,
+            (WAVE_FORMAT_PCM, 24) => {
+                |b| (i32::from_le_bytes([0, b[0], b[1], b[2]]) >> 8) as f32 / 8_388_608.0
+            }
+            (WAVE_FORMAT_PCM, 32) => {
+                |b| i32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f32 / 2_147_483_648.0
+            }
+            (WAVE_FORMAT_IEEE_FLOAT, 32) => |b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]),
+            (WAVE_FORMAT_IEEE_FLOAT, 64) => {
+                |b| f64::from_le_bytes([b[0], b[1], b[2], b[3], b[4], b[5], b[6], b[7]]) as f32
+            }
+            (format, bits) => return Err(WavError::UnsupportedFormat { format, bits }),
+        };
+
+        let sample_size = bits as usize / 8;
+        let mut channels = vec![Vec::new(); channel_count];
+        if channel_count > 0 {
+            for frame in data.chunks_exact(sample_size * channel_count) {
+                for (channel, sample) in channels.iter_mut().zip(frame.chunks_exact(sample_size)) {
+                    channel.push(decode(sample));
+                }
+            }
+        }
+
+        Ok(Self {
+            sample_rate,
+            channels,
+        })
+    }
+
+    /// Write a 32 bits float WAV file. Fails without creating the file when the sizes
+    /// don't fit the 32 bits fields of the format (e.g. more than 4 GiB of samples).
+    pub fn write(&self, path: &Path) -> std::io::Result<()> {
+        let too_large = |what: &str| {
+            std::io::Error::new(
+                ErrorKind::InvalidInput,
+                format!("{} too large for a WAV file", what),
+            )
+        };
+        let block_align =
+            u16::try_from(self.channels.len() * 4).map_err(|_| too_large("Channel count"))?;
+        let channel_count = block_align / 4;
+        let frames = self.channels.first().map_or(0, Vec::len);
+        let frame_count = u32::try_from(frames).map_err(|_| too_large("Frame count"))?;
+        let data_size = frame_count
+            .checked_mul(block_align as u32)
+            // fmt (8 + 18), fact (8 + 4) and data (8 + data_size) chunks after "WAVE"
+            .filter(|data_size| data_size.checked_add(4 + 26 + 12 + 8).is_some())
+            .ok_or_else(|| too_large("Audio data (over 4 GiB)"))?;
+        let byte_rate = self
+            .sample_rate
+            .checked_mul(block_align as u32)
+            .ok_or_else(|| too_large("Byte rate"))?;
+
+        let mut file = BufWriter::new(File::create(path)?);
+        file.write_all(b"RIFF")?;
+        file.write_all(&(4 + 26 + 12 + 8 + data_size).to_le_bytes())?;
+        file.write_all(b"WAVE")?;
+
+        file.write_all(b"fmt ")?;
+        file.write_all(&18_u32.to_le_bytes())?;
+        file.write_all(&WAVE_FORMAT_IEEE_FLOAT.to_le_bytes())?;
+        file.write_all(&channel_count.to_le_bytes())?;
+        file.write_all(&self.sample_rate.to_le_bytes())?;
+        file.write_all(&byte_rate.to_le_bytes())?;
+        file.write_all(&block_align.to_le_bytes())?;
+        file.write_all(&32_u16.to_le_bytes())?;
+        // No extra format bytes
+        file.write_all(&0_u16.to_le_bytes())?;
+
+        // Non PCM formats need a fact chunk with the number of frames
+        file.write_all(b"fact")?;
+        file.write_all(&4_u32.to_le_bytes())?;
+        file.write_all(&frame_count.to_le_bytes())?;
+
+        file.write_all(b"data")?;
+        file.write_all(&data_size.to_le_bytes())?;
+        for frame in 0..frames {
+            for channel in &self.channels {
+                file.write_all(&channel[frame].to_le_bytes())?;
+            }
+        }
+        file.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::temp_dir;
+
+    fn temp_path(name: &str) -> PathBuf {
+        temp_dir(name).join("audio.wav")
+    }
+
+    #[test]
+    fn reads_back_written_files() {
+        let path = temp_path("wav-roundtrip");
+        let wav = WavAudio {
+            sample_rate: 44_100,
+            channels: vec![vec![0.0, 0.5, -1.0], vec![0.25, -0.25, 1.0]],
+        };
+        wav.write(&path).unwrap();
+
+        let read = WavAudio::read(&path).unwrap();
+        assert_eq!(read.sample_rate, 44_100);
+        assert_eq!(read.channels, wav.channels);
+        // RIFF size = file size - 8
+        let bytes = std::fs::read(&path).unwrap();
+        assert_eq!(
+            u32::from_le_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]) as usize,
+            bytes.len() - 8
+        );
+
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn refuses_sizes_that_do_not_fit() {
+        let path = temp_path("wav-too-large");
+        let too_many_channels = WavAudio {
+            sample_rate: 48_000,
+            channels: vec![Vec::new(); 20_000],
+        };
+        let error = too_many_channels.write(&path).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidInput);
+
+        let too_fast = WavAudio {
+            sample_rate: u32::MAX,
+            channels: vec![vec![0.0]; 2],
+        };
+        assert!(too_fast.write(&path).is_err());
+        assert!(!path.exists());
+
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn rejects_files_that_are_not_wave() {
+        let path = temp_path("wav-invalid");
+        std::fs::write(&path, b"RIFF\0\0\0\0AVI ").unwrap();
+        assert!(matches!(WavAudio::read(&path), Err(WavError::NotWave)));
+
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+}
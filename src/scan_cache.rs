@@ -0,0 +1,340 @@
+use std::{
+    collections::HashMap,
+    io::Read,
+    path::{Path, PathBuf},
+    time::UNIX_EPOCH,
+};
+
+use crate::InfoBundle;
+
+pub(crate) const SCAN_CACHE_FILE: &str = "scan-cache.json";
+
+// Bump whenever the cache file layout (or the InfoBundle json) changes
+const SCAN_CACHE_VERSION: u32 = 12;
+
+/// An on-disk cache of bundle scan results.
+///
+/// Entries are keyed by the canonical path of the bundle, and are only reused
+/// while the bundle's content hash is unchanged. The content is only hashed again
+/// when the bundle's size or modification time changed.
+pub struct ScanCache {
+    file: PathBuf,
+    entries: ScanCacheEntries,
+    // Fingerprints computed by `get` on a miss, for the following `insert`
+    fingerprints: HashMap<String, BundleFingerprint>,
+}
+
+type ScanCacheEntries = HashMap<String, ScanCacheEntry>;
+
+#[derive(serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct ScanCacheFile<E> {
+    version: u32,
+    entries: E,
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct ScanCacheEntry {
+    fingerprint: BundleFingerprint,
+    bundle: InfoBundle,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct BundleFingerprint {
+    size: u64,
+    modified_ns: u128,
+    content_hash: String,
+}
+
+impl BundleFingerprint {
+    /// Fingerprint a bundle file, or every file inside a bundle directory.
+    ///
+    /// Hashing a whole bundle is slow, so when its size and modification time match
+    /// the `known` fingerprint, that one is returned as is.
+    pub fn from_path(path: &Path, known: Option<&BundleFingerprint>) -> std::io::Result<Self> {
+        let mut files = Vec::new();
+        Self::collect_files(path, &mut files)?;
+        files.sort();
+
+        let mut size = 0;
+        let mut modified_ns = 0;
+        for file in &files {
+            let metadata = std::fs::metadata(file)?;
+            size += metadata.len();
+            let modified = metadata
+                .modified()?
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_nanos();
+            modified_ns = modified_ns.max(modified);
+        }
+        if let Some(known) =
+            known.filter(|known| known.size == size && known.modified_ns == modified_ns)
+        {
+            return Ok(known.clone());
+        }
+
+        let mut hasher = Fnv1a::new();
+        for file in &files {
+            // Include the relative path so renames inside a bundle are noticed
+            let relative = file.strip_prefix(path).unwrap_or(file);
+            hasher.write(relative.to_string_lossy().as_bytes());
+
+            let mut content = std::fs::File::open(file)?;
+            let mut buffer = [0; 64 * 1024];
+            loop {
+                let read = content.read(&mut buffer)?;
+                if read == 0 {
+                    break;
+                }
+                hasher.write(&buffer[..read]);
+            }
+        }
+
+        Ok(Self {
+            size,
+            modified_ns,
+            content_hash: format!("{:016x}", hasher.finish()),
+        })
+    }
+
+    fn collect_files(path: &Path, files: &mut Vec<PathBuf>) -> std::io::Result<()> {
+        if path.is_dir() {
+            for entry in std::fs::read_dir(path)? {
+                Self::collect_files(&entry?.path(), files)?;
+            }
+        } else {
+            files.push(path.to_path_buf());
+        }
+        Ok(())
+    }
+}
+
+impl ScanCache {
+    /// The default cache directory: `$XDG_CACHE_HOME/clap-info-rs`, falling back to
+    /// `~/.cache/clap-info-rs`.
+    pub fn default_dir() -> PathBuf {
+        let cache_home = std::env::var_os("XDG_CACHE_HOME")
+            .filter(|dir| !dir.is_empty())
+            .map(PathBuf::from)
+            .unwrap_or_else(|| PathBuf::from(shellexpand::tilde("~/.cache").to_string()));
+        cache_home.join("clap-info-rs")
+    }
+
+    pub fn open_default() -> Self {
+        Self::open(&Self::default_dir())
+    }
+
+    /// Open the cache stored in `dir`. A missing, unreadable or outdated cache file
+    /// results in an empty cache.
+    pub fn open(dir: &Path) -> Self {
+        let file = dir.join(SCAN_CACHE_FILE);
+        let entries = std::fs::read(&file)
+            .ok()
+            .and_then(|content| {
+                serde_json::from_slice::<ScanCacheFile<ScanCacheEntries>>(&content).ok()
+            })
+            .filter(|cache| cache.version == SCAN_CACHE_VERSION)
+            .map(|cache| cache.entries)
+            .unwrap_or_default();
+
+        Self {
+            file,
+            entries,
+            fingerprints: HashMap::new(),
+        }
+    }
+
+    /// Returns the cached scan result of a bundle, if the bundle didn't change since.
+    ///
+    /// A bundle that was touched but whose content didn't change is still a hit. On a
+    /// miss, the fingerprint is kept so [`ScanCache::insert`] doesn't hash the bundle again.
+    pub fn get(&mut self, clap_path: &Path) -> Option<&InfoBundle> {
+        let key = Self::key(clap_path);
+        let known = self.entries.get(&key).map(|entry| &entry.fingerprint);
+        let fingerprint = BundleFingerprint::from_path(clap_path, known).ok()?;
+        match self.entries.get_mut(&key) {
+            Some(entry) if entry.fingerprint.content_hash == fingerprint.content_hash => {
+                // Remember the new size and modification time to skip hashing next time
+                entry.fingerprint = fingerprint;
+                Some(&entry.bundle)
+            }
+            _ => {
+                self.fingerprints.insert(key, fingerprint);
+                None
+            }
+        }
+    }
+
+    pub fn insert(&mut self, clap_path: &Path, bundle: InfoBundle) {
+        let key = Self::key(clap_path);
+        let fingerprint = match self.fingerprints.remove(&key) {
+            Some(fingerprint) => Ok(fingerprint),
+            None => BundleFingerprint::from_path(clap_path, None),
+        };
+        if let Ok(fingerprint) = fingerprint {
+            self.entries.insert(
+                key,
+                ScanCacheEntry {
+                    fingerprint,
+                    bundle,
+                },
+            );
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.fingerprints.clear();
+    }
+
+    /// Every cached bundle, by canonical path. Entries aren't checked against the
+    /// file system: use [`ScanCache::get`] for up-to-date results.
+    pub fn bundles(&self) -> impl Iterator<Item = (&str, &InfoBundle)> {
+        self.entries
+            .iter()
+            .map(|(path, entry)| (path.as_str(), &entry.bundle))
+    }
+
+    /// Write the cache to disk, creating the cache directory if needed. Entries of
+    /// bundles that no longer exist are dropped.
+    pub fn save(&self) -> std::io::Result<()> {
+        if let Some(dir) = self.file.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+
+        let cache = ScanCacheFile {
+            version: SCAN_CACHE_VERSION,
+            entries: self
+                .entries
+                .iter()
+                .filter(|(path, _)| Path::new(path).exists())
+                .collect::<HashMap<_, _>>(),
+        };
+
+        // Write to a temporary file first so a concurrent reader never sees half a cache
+        let tmp_file = self.file.with_extension("json.tmp");
+        std::fs::write(&tmp_file, serde_json::to_vec(&cache)?)?;
+        std::fs::rename(&tmp_file, &self.file)
+    }
+
+    fn key(clap_path: &Path) -> String {
+        clap_path
+            .canonicalize()
+            .unwrap_or_else(|_| clap_path.to_path_buf())
+            .display()
+            .to_string()
+    }
+}
+
+// 64-bit FNV-1a: simple, dependency free and stable across Rust versions
+// (unlike `DefaultHasher`), which matters for a hash persisted on disk.
+struct Fnv1a(u64);
+
+impl Fnv1a {
+    fn new() -> Self {
+        Self(0xcbf29ce484222325)
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 ^= *byte as u64;
+            self.0 = self.0.wrapping_mul(0x100000001b3);
+        }
+    }
+
+    fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::temp_dir;
+
+    fn bundle(path: &Path) -> InfoBundle {
+        serde_json::from_value(serde_json::json!({
+            "clap-version": "1.2.0",
+            "path": path.display().to_string(),
+            "plugins": [],
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn fnv1a_matches_reference_values() {
+        let hash = |bytes: &[u8]| {
+            let mut hasher = Fnv1a::new();
+            hasher.write(bytes);
+            hasher.finish()
+        };
+        assert_eq!(hash(b""), 0xcbf29ce484222325);
+        assert_eq!(hash(b"a"), 0xaf63dc4c8601ec8c);
+        assert_eq!(hash(b"foobar"), 0x85944171f73967e8);
+    }
+
+    #[test]
+    fn fingerprint_hashes_only_changed_bundles() {
+        let dir = temp_dir("fingerprint");
+        let file = dir.join("plugin.clap");
+        std::fs::write(&file, b"first").unwrap();
+
+        let fingerprint = BundleFingerprint::from_path(&file, None).unwrap();
+        // Same size and time: the known fingerprint is trusted without hashing
+        let known = BundleFingerprint {
+            content_hash: "not hashed".to_string(),
+            ..fingerprint.clone()
+        };
+        assert_eq!(
+            BundleFingerprint::from_path(&file, Some(&known)).unwrap(),
+            known
+        );
+
+        std::fs::write(&file, b"second").unwrap();
+        let changed = BundleFingerprint::from_path(&file, Some(&fingerprint)).unwrap();
+        assert_ne!(changed.content_hash, fingerprint.content_hash);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn key_is_the_canonical_path() {
+        let dir = temp_dir("key");
+        let file = dir.join("plugin.clap");
+        std::fs::write(&file, b"").unwrap();
+        std::fs::create_dir(dir.join("sub")).unwrap();
+
+        let indirect = dir.join("sub").join("..").join("plugin.clap");
+        assert_eq!(ScanCache::key(&indirect), ScanCache::key(&file));
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn reuses_unchanged_bundles_and_drops_deleted_ones() {
+        let dir = temp_dir("cache");
+        let kept = dir.join("kept.clap");
+        let deleted = dir.join("deleted.clap");
+        std::fs::write(&kept, b"kept").unwrap();
+        std::fs::write(&deleted, b"deleted").unwrap();
+
+        let mut cache = ScanCache::open(&dir);
+        assert!(cache.get(&kept).is_none());
+        cache.insert(&kept, bundle(&kept));
+        cache.insert(&deleted, bundle(&deleted));
+        std::fs::remove_file(&deleted).unwrap();
+        cache.save().unwrap();
+
+        let mut cache = ScanCache::open(&dir);
+        assert!(cache.get(&kept).is_some());
+        assert_eq!(cache.bundles().count(), 1);
+
+        std::fs::write(&kept, b"changed").unwrap();
+        assert!(cache.get(&kept).is_none());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,9 @@
+use std::path::PathBuf;
+
+// A fresh, empty directory under the system temp directory
+pub(crate) fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("clap-info-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
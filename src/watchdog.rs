@@ -0,0 +1,113 @@
+use std::{
+    sync::{Arc, Mutex, mpsc},
+    time::{Duration, Instant},
+};
+
+// How often the waiting thread checks the phase timer
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Keeps track of the lifecycle phase (bundle loading, instantiation, activation,
+/// extension probes...) a worker thread is currently in, so that a phase taking
+/// too long can be detected and named.
+#[derive(Debug, Clone)]
+pub struct Watchdog {
+    state: Arc<Mutex<WatchdogState>>,
+}
+
+#[derive(Debug)]
+struct WatchdogState {
+    phase: &'static str,
+    entered_at: Instant,
+}
+
+impl Default for Watchdog {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Watchdog {
+    pub fn new() -> Self {
+        Self {
+            state: Arc::new(Mutex::new(WatchdogState {
+                phase: "start",
+                entered_at: Instant::now(),
+            })),
+        }
+    }
+
+    /// Start a new phase. Each phase gets the full timeout for itself.
+    pub fn enter(&self, phase: &'static str) {
+        let mut state = self.state.lock().unwrap();
+        state.phase = phase;
+        state.entered_at = Instant::now();
+    }
+
+    pub fn phase(&self) -> &'static str {
+        self.state.lock().unwrap().phase
+    }
+
+    fn expired_phase(&self, timeout: Duration) -> Option<&'static str> {
+        let state = self.state.lock().unwrap();
+        (state.entered_at.elapsed() >= timeout).then_some(state.phase)
+    }
+}
+
+#[derive(Debug, Clone, thiserror::Error, serde::Serialize)]
+#[error("Timed out after {timeout_ms}ms in {timed_out_in}")]
+#[serde(rename_all = "kebab-case")]
+pub struct TimedOut {
+    timed_out_in: &'static str,
+    timeout_ms: u64,
+}
+
+impl TimedOut {
+    pub fn timed_out_in(&self) -> &'static str {
+        self.timed_out_in
+    }
+}
+
+/// Run `work` on a worker thread, failing as soon as any of the phases it enters
+/// on the given [`Watchdog`] lasts longer than `timeout`.
+///
+/// A plugin call can't be interrupted, so on timeout the worker thread is left
+/// behind: callers are expected to move on (or exit the process).
+/// Without a timeout `work` simply runs on the current thread.
+pub fn run_with_timeout<T, F>(timeout: Option<Duration>, work: F) -> Result<T, TimedOut>
+where
+    T: Send + 'static,
+    F: FnOnce(&Watchdog) -> T + Send + 'static,
+{
+    let watchdog = Watchdog::new();
+
+    let Some(timeout) = timeout else {
+        return Ok(work(&watchdog));
+    };
+
+    let (sender, receiver) = mpsc::channel();
+    let worker_watchdog = watchdog.clone();
+    let worker = std::thread::spawn(move || {
+        let _ = sender.send(work(&worker_watchdog));
+    });
+
+    loop {
+        match receiver.recv_timeout(POLL_INTERVAL.min(timeout)) {
+            Ok(result) => return Ok(result),
+            Err(mpsc::RecvTimeoutError::Timeout) => {
+                if let Some(phase) = watchdog.expired_phase(timeout) {
+                    return Err(TimedOut {
+                        timed_out_in: phase,
+                        timeout_ms: timeout.as_millis() as u64,
+                    });
+                }
+            }
+            Err(mpsc::RecvTimeoutError::Disconnected) => {
+                // The worker panicked: propagate it to the caller
+                match worker.join() {
+                    Err(panic) => std::panic::resume_unwind(panic),
+                    Ok(()) => unreachable!("worker always sends its result"),
+                }
+            }
+        }
+    }
+}